@@ -21,6 +21,9 @@ impl TryFrom<&Token> for BinaryOperator {
             TokenType::Minus => Ok(Subtraction),
             TokenType::Star => Ok(Multiplication),
             TokenType::Slash => Ok(Division),
+            TokenType::Percent => Ok(Modulo),
+            TokenType::And => Ok(Conjunction),
+            TokenType::Or => Ok(Disjunction),
             _ => Err(Error::new(
                 "not a binary operator".to_string(),
                 value.get_span(),
@@ -35,6 +38,7 @@ impl TryFrom<&Token> for UnaryOperator {
     fn try_from(value: &Token) -> Result<Self, Self::Error> {
         match *value.get_type() {
             TokenType::Minus => Ok(Minus),
+            TokenType::Plus => Ok(Plus),
             TokenType::Bang => Ok(Not),
             _ => Err(Error::new(
                 "not an unary operator".to_string(),
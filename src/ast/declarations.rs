@@ -6,12 +6,13 @@ use std::{
 use crate::ast::{
     expressions::{Expression, Identifier},
     types::Function,
-    LiteralValue,
 };
 
 pub struct VariableDeclaration {
     pub name: Identifier,
-    pub initializer: Expression,
+    /// The initial value, if the declaration has one. Variables declared
+    /// without one are initialized to nil.
+    pub initializer: Option<Expression>,
 }
 
 impl Display for VariableDeclaration {
@@ -21,8 +22,8 @@ impl Display for VariableDeclaration {
             "var {}{};",
             self.name.ident,
             match &self.initializer {
-                Expression::Literal(l) if l.value == LiteralValue::Nil => "".to_string(),
-                expr => format!(" = {}", expr),
+                Some(expr) => format!(" = {}", expr),
+                None => "".to_string(),
             }
         )
     }
@@ -42,12 +43,17 @@ impl Display for FunctionDeclaration {
 
 pub struct ClassDeclaration {
     pub name: Identifier,
+    pub superclass: Option<Identifier>,
     pub methods: Vec<FunctionDeclaration>,
 }
 
 impl Display for ClassDeclaration {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "class {} {{", self.name)?;
+        write!(f, "class {}", self.name)?;
+        if let Some(superclass) = &self.superclass {
+            write!(f, " < {}", superclass)?;
+        }
+        writeln!(f, " {{")?;
         for method in &self.methods {
             writeln!(f, "{}{}", method.name, method.function)?;
         }
@@ -1,4 +1,7 @@
-use std::fmt::{Display, Formatter};
+use std::{
+    cell::Cell,
+    fmt::{Display, Formatter},
+};
 
 use crate::{ast::LiteralValue, code_span::CodeSpan};
 
@@ -11,6 +14,8 @@ pub enum Expression {
     Call(Call),
     Get(Get),
     Set(Set),
+    This(CodeSpan),
+    Super(Super),
 }
 
 #[derive(Clone)]
@@ -28,6 +33,8 @@ pub struct Unary {
 #[derive(Copy, Clone)]
 pub enum UnaryOperator {
     Minus,
+    /// Unary plus, which leaves numbers as they are.
+    Plus,
     Not,
 }
 
@@ -38,7 +45,7 @@ pub struct Binary {
     pub location: CodeSpan,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum BinaryOperator {
     Equality,
     Inequality,
@@ -50,6 +57,7 @@ pub enum BinaryOperator {
     Subtraction,
     Multiplication,
     Division,
+    Modulo,
     Conjunction,
     Disjunction,
 }
@@ -64,6 +72,24 @@ pub struct Assignment {
 pub struct Identifier {
     pub ident: String,
     pub location: CodeSpan,
+    /// Where the variable is found, set by the resolver when the identifier
+    /// is used as a variable.
+    pub binding: Cell<Binding>,
+}
+
+/// Where a variable is found when it is evaluated.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Binding {
+    /// The resolver did not run, so the variable is looked up from the
+    /// innermost scope outwards.
+    #[default]
+    Unresolved,
+    /// The variable is in a local scope, this many scopes out from the
+    /// innermost one.
+    Local(usize),
+    /// The variable is not declared in any enclosing local scope, so it is a
+    /// global.
+    Global,
 }
 
 pub struct Call {
@@ -72,6 +98,7 @@ pub struct Call {
     pub location: CodeSpan,
 }
 
+/// A property access, located from its object to the name of the property.
 pub struct Get {
     pub object: Box<Expression>,
     pub name: Identifier,
@@ -85,6 +112,12 @@ pub struct Set {
     pub location: CodeSpan,
 }
 
+/// A method of the superclass, as in `super.method`.
+pub struct Super {
+    pub method: Identifier,
+    pub location: CodeSpan,
+}
+
 impl Expression {
     pub fn get_location(&self) -> CodeSpan {
         match self {
@@ -96,8 +129,86 @@ impl Expression {
             Expression::Call(c) => c.location,
             Expression::Get(g) => g.location,
             Expression::Set(s) => s.location,
+            Expression::This(location) => *location,
+            Expression::Super(s) => s.location,
         }
     }
+
+    /// Returns the span of the whole expression, from its first token to its
+    /// last, whereas `get_location` only points at the operator or the name
+    /// of some expressions. Parentheses are not kept in the tree, so the span
+    /// only includes those inside the expression.
+    pub fn span(&self) -> CodeSpan {
+        match self {
+            Expression::UnaryOperation(u) => CodeSpan::combine(u.location, u.expr.span()),
+            Expression::BinaryOperation(b) => CodeSpan::combine(b.left.span(), b.right.span()),
+            Expression::Assignment(a) => CodeSpan::combine(a.ident.location, a.expr.span()),
+            Expression::Call(c) => CodeSpan::combine(c.callee.span(), c.location),
+            Expression::Get(g) => CodeSpan::combine(g.object.span(), g.name.location),
+            Expression::Set(s) => CodeSpan::combine(s.object.span(), s.value.span()),
+            expr => expr.get_location(),
+        }
+    }
+
+    /// Returns the expression as written in its source, given as its lines,
+    /// instead of the normalized form of `Display`. Parentheses around
+    /// operands are kept, but not those around the whole expression. An
+    /// expression that is not from the source is displayed instead.
+    pub fn source_text(&self, source: &[&str]) -> String {
+        let span = self.span();
+        if span.is_synthetic() {
+            return self.to_string();
+        }
+        let text = source.join("\n").chars().collect::<Vec<_>>();
+        let index = |line: usize, char: usize| {
+            source[..line - 1]
+                .iter()
+                .map(|line| line.chars().count() + 1)
+                .sum::<usize>()
+                + char
+        };
+        let mut start = index(span.start.line, span.start.char);
+        let mut end = index(span.end.line, span.end.char);
+
+        // Widen the text over the parentheses around the first and last
+        // operands, which are outside of the span.
+        let (unclosed, unopened) = unbalanced_parentheses(&text[start..end]);
+        for _ in 0..unopened {
+            match text[..start].iter().rposition(|c| !c.is_whitespace()) {
+                Some(i) if text[i] == '(' => start = i,
+                _ => break,
+            }
+        }
+        for _ in 0..unclosed {
+            match text[end..].iter().position(|c| !c.is_whitespace()) {
+                Some(i) if text[end + i] == ')' => end += i + 1,
+                _ => break,
+            }
+        }
+        text[start..end].iter().collect()
+    }
+}
+
+/// Returns the number of opening parentheses that are not closed and of
+/// closing parentheses that are not opened in some code, ignoring those in
+/// string literals.
+fn unbalanced_parentheses(code: &[char]) -> (usize, usize) {
+    let (mut depth, mut unopened) = (0, 0);
+    let mut chars = code.iter();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string && depth > 0 => depth -= 1,
+            ')' if !in_string => unopened += 1,
+            _ => (),
+        }
+    }
+    (depth, unopened)
 }
 
 impl Literal {
@@ -125,6 +236,7 @@ impl Priority for BinaryOperator {
             BinaryOperator::Subtraction => 4,
             BinaryOperator::Multiplication => 5,
             BinaryOperator::Division => 5,
+            BinaryOperator::Modulo => 5,
         }
     }
 }
@@ -165,6 +277,24 @@ impl Priority for Assignment {
     }
 }
 
+impl Priority for Call {
+    fn priority(&self) -> u8 {
+        7
+    }
+}
+
+impl Priority for Get {
+    fn priority(&self) -> u8 {
+        7
+    }
+}
+
+impl Priority for Set {
+    fn priority(&self) -> u8 {
+        7
+    }
+}
+
 impl Priority for Expression {
     fn priority(&self) -> u8 {
         match self {
@@ -173,7 +303,10 @@ impl Priority for Expression {
             Expression::BinaryOperation(b) => b.operator.priority(),
             Expression::Identifier(i) => i.priority(),
             Expression::Assignment(a) => a.priority(),
-            Expression::Call(_) | Expression::Get(_) | Expression::Set(_) => 7,
+            Expression::Call(c) => c.priority(),
+            Expression::Get(g) => g.priority(),
+            Expression::Set(s) => s.priority(),
+            Expression::This(_) | Expression::Super(_) => 8,
         }
     }
 }
@@ -214,10 +347,35 @@ impl ExpressionNode for Assignment {
     }
 }
 
+impl ExpressionNode for Call {
+    fn accept<T: ExpressionVisitor>(&self, visitor: &mut T) -> T::Return {
+        visitor.visit_call(self)
+    }
+}
+
+impl ExpressionNode for Super {
+    fn accept<T: ExpressionVisitor>(&self, visitor: &mut T) -> T::Return {
+        visitor.visit_super(self)
+    }
+}
+
+impl ExpressionNode for Get {
+    fn accept<T: ExpressionVisitor>(&self, visitor: &mut T) -> T::Return {
+        visitor.visit_get(self)
+    }
+}
+
+impl ExpressionNode for Set {
+    fn accept<T: ExpressionVisitor>(&self, visitor: &mut T) -> T::Return {
+        visitor.visit_set(self)
+    }
+}
+
 impl Display for UnaryOperator {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let c = match self {
             Self::Minus => '-',
+            Self::Plus => '+',
             Self::Not => '!',
         };
         write!(f, "{}", c)
@@ -237,6 +395,7 @@ impl Display for BinaryOperator {
             Self::Inferiority => "<=",
             Self::Subtraction => "-",
             Self::Multiplication => "*",
+            Self::Modulo => "%",
             Self::Conjunction => "and",
             Self::Disjunction => "or",
         };
@@ -272,10 +431,30 @@ impl Display for Binary {
     }
 }
 
+/// Writes a string the way it appears in a string literal, so that it scans
+/// back to the same string.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '\0' => escaped.push_str("\\0"),
+            '\\' | '"' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 impl Display for Literal {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let s = match &self.value {
-            LiteralValue::StringLiteral(s) => format!("\"{}\"", s),
+            LiteralValue::StringLiteral(s) => format!("\"{}\"", escape(s)),
             LiteralValue::NumberLiteral(f) => format!("{}", f),
             LiteralValue::Nil => "nil".to_string(),
             LiteralValue::True => "true".to_string(),
@@ -293,7 +472,7 @@ impl Display for Assignment {
 
 impl Display for Call {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}(", self.callee)?;
+        write!(f, "{}(", postfix_operand(&self.callee))?;
         let mut it = self.arguments.iter();
         if let Some(argument) = it.next() {
             write!(f, "{}", argument)?;
@@ -305,6 +484,34 @@ impl Display for Call {
     }
 }
 
+/// Formats the callee of a call or the object of a property access, which
+/// needs parentheses unless it binds at least as tightly as a call.
+fn postfix_operand(expr: &Expression) -> String {
+    if expr.priority() < 7 {
+        format!("({})", expr)
+    } else {
+        expr.to_string()
+    }
+}
+
+impl Display for Get {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", postfix_operand(&self.object), self.name)
+    }
+}
+
+impl Display for Set {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{} = {}",
+            postfix_operand(&self.object),
+            self.name,
+            self.value
+        )
+    }
+}
+
 impl Display for Expression {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -314,8 +521,10 @@ impl Display for Expression {
             Self::Identifier(i) => write!(f, "{}", i.ident),
             Self::Assignment(a) => write!(f, "{}", a),
             Self::Call(call) => write!(f, "{}", call),
-            Self::Get(g) => write!(f, "{}.{}", g.object, g.name),
-            Self::Set(s) => write!(f, "{}.{} = {}", s.object, s.name, s.value),
+            Self::Get(g) => write!(f, "{}", g),
+            Self::Set(s) => write!(f, "{}", s),
+            Self::This(_) => write!(f, "this"),
+            Self::Super(s) => write!(f, "super.{}", s.method),
         }
     }
 }
@@ -340,9 +549,11 @@ pub trait ExpressionVisitor: Sized {
             Expression::BinaryOperation(b) => b.accept(self),
             Expression::Identifier(i) => i.accept(self),
             Expression::Assignment(a) => a.accept(self),
-            Expression::Call(c) => self.visit_call(c),
-            Expression::Get(g) => self.visit_get(g),
-            Expression::Set(s) => self.visit_set(s),
+            Expression::Call(c) => c.accept(self),
+            Expression::Get(g) => g.accept(self),
+            Expression::Set(s) => s.accept(self),
+            Expression::This(location) => self.visit_this(*location),
+            Expression::Super(s) => s.accept(self),
         }
     }
 
@@ -354,4 +565,105 @@ pub trait ExpressionVisitor: Sized {
     fn visit_call(&mut self, call: &Call) -> Self::Return;
     fn visit_get(&mut self, get: &Get) -> Self::Return;
     fn visit_set(&mut self, set: &Set) -> Self::Return;
+    fn visit_this(&mut self, location: CodeSpan) -> Self::Return;
+    fn visit_super(&mut self, expr: &Super) -> Self::Return;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parsing::parse_expression, scanning::TokenStream};
+
+    /// Counts the calls in an expression, and every node visited.
+    #[derive(Default)]
+    struct CallCounter {
+        calls: usize,
+        nodes: usize,
+    }
+
+    impl ExpressionVisitor for CallCounter {
+        type Return = ();
+
+        fn visit_literal(&mut self, _: &Literal) {
+            self.nodes += 1;
+        }
+
+        fn visit_unary(&mut self, unary: &Unary) {
+            self.nodes += 1;
+            self.visit_expression(&unary.expr);
+        }
+
+        fn visit_binary(&mut self, binary: &Binary) {
+            self.nodes += 1;
+            self.visit_expression(&binary.left);
+            self.visit_expression(&binary.right);
+        }
+
+        fn visit_identifier(&mut self, _: &Identifier) {
+            self.nodes += 1;
+        }
+
+        fn visit_assignment(&mut self, assignment: &Assignment) {
+            self.nodes += 1;
+            self.visit_expression(&assignment.expr);
+        }
+
+        fn visit_call(&mut self, call: &Call) {
+            self.nodes += 1;
+            self.calls += 1;
+            self.visit_expression(&call.callee);
+            for argument in &call.arguments {
+                self.visit_expression(argument);
+            }
+        }
+
+        fn visit_get(&mut self, get: &Get) {
+            self.nodes += 1;
+            self.visit_expression(&get.object);
+        }
+
+        fn visit_set(&mut self, set: &Set) {
+            self.nodes += 1;
+            self.visit_expression(&set.object);
+            self.visit_expression(&set.value);
+        }
+
+        fn visit_this(&mut self, _: CodeSpan) {
+            self.nodes += 1;
+        }
+
+        fn visit_super(&mut self, _: &Super) {
+            self.nodes += 1;
+        }
+    }
+
+    #[test]
+    fn source_text() {
+        for code in [
+            "a  +  b",
+            "(a + b) * c",
+            "a * (b + c)",
+            "(a) + (b)",
+            "-(x)",
+            "f( 1,2 ).x = ( 3 )",
+            "\"(\" + s",
+            "a.b\n  .c(\n  d)",
+            "super.m(this)",
+        ] {
+            let expr = parse_expression(&mut TokenStream::new(code)).unwrap();
+            assert_eq!(expr.source_text(&code.lines().collect::<Vec<_>>()), code);
+        }
+        let code = "(a + b)";
+        let expr = parse_expression(&mut TokenStream::new(code)).unwrap();
+        assert_eq!(expr.source_text(&[code]), "a + b");
+    }
+
+    #[test]
+    fn visitor_reaches_calls_and_gets() {
+        let expr = parse_expression(&mut TokenStream::new("a.b(f(1), -x).c = g()")).unwrap();
+        let mut counter = CallCounter::default();
+        counter.visit_expression(&expr);
+        assert_eq!(counter.calls, 3);
+        assert_eq!(counter.nodes, 11);
+    }
 }
@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 mod conversions;
 pub mod declarations;
 pub mod expressions;
@@ -6,7 +8,9 @@ pub mod types;
 
 #[derive(PartialEq, Clone)]
 pub enum LiteralValue {
-    StringLiteral(String),
+    /// Shared with the values the literal evaluates to, so that evaluating it
+    /// repeatedly does not allocate.
+    StringLiteral(Rc<String>),
     NumberLiteral(f64),
     True,
     False,
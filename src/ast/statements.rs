@@ -1,27 +1,135 @@
 use std::fmt::{Debug, Display, Formatter};
 
 use super::declarations::ClassDeclaration;
-use crate::ast::{
-    declarations::{FunctionDeclaration, VariableDeclaration},
-    expressions::Expression,
-    LiteralValue,
+use crate::{
+    ast::{
+        declarations::{FunctionDeclaration, VariableDeclaration},
+        expressions::{Expression, Identifier},
+        LiteralValue,
+    },
+    code_span::CodeSpan,
 };
 
 pub enum Statement {
-    Print(Expression),
+    Print(Print),
+    Assert(Assert),
     Expression(Expression),
     VariableDeclaration(VariableDeclaration),
     ClassDeclaration(ClassDeclaration),
     Block(Statements),
     Conditional(Box<Conditional>),
     WhileLoop(Box<WhileLoop>),
-    ForLoop(Box<ForLoop>),
+    ForIn(Box<ForIn>),
     FunctionDeclaration(FunctionDeclaration),
     Return(Expression),
+    Break(CodeSpan),
+    Continue(CodeSpan),
 }
 
+/// A list of statements. The closing brace is recorded for blocks and function
+/// bodies parsed from the source, so that comments before it stay inside.
+#[derive(Default)]
 pub struct Statements {
     pub stmts: Vec<Statement>,
+    pub closing_brace: Option<CodeSpan>,
+}
+
+impl Statements {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, stmt: Statement) {
+        self.stmts.push(stmt);
+    }
+
+    /// Returns the span from the first statement to the last one, or a
+    /// synthetic span if there are no statements.
+    pub fn get_location(&self) -> CodeSpan {
+        match (self.stmts.first(), self.stmts.last()) {
+            (Some(first), Some(last)) => {
+                CodeSpan::combine(first.get_location(), last.get_location())
+            }
+            _ => CodeSpan::synthetic(),
+        }
+    }
+}
+
+impl Statement {
+    /// Returns the span of the statement. Keywords and punctuation are only
+    /// included when the statement records their position, so the span might
+    /// only cover the names, expressions and nested statements.
+    pub fn get_location(&self) -> CodeSpan {
+        match self {
+            Statement::Print(print) => print.location,
+            Statement::Assert(assert) => assert.location,
+            Statement::Expression(expr) => expr.get_location(),
+            Statement::VariableDeclaration(decl) => match &decl.initializer {
+                Some(initializer) => {
+                    CodeSpan::combine(decl.name.location, initializer.get_location())
+                }
+                None => decl.name.location,
+            },
+            Statement::ClassDeclaration(decl) => match decl.methods.last() {
+                Some(method) => {
+                    CodeSpan::combine(decl.name.location, Statement::function_location(method))
+                }
+                None => decl.name.location,
+            },
+            Statement::Block(stmts) => stmts.get_location(),
+            Statement::Conditional(c) => {
+                let last = c.else_statement.as_ref().unwrap_or(&c.then_statement);
+                CodeSpan::combine(c.condition.get_location(), last.get_location())
+            }
+            Statement::WhileLoop(w) => {
+                let last = w.else_statement.as_ref().unwrap_or(&w.statement);
+                CodeSpan::combine(w.condition.get_location(), last.get_location())
+            }
+            Statement::ForIn(l) => {
+                let last = l.else_statement.as_ref().unwrap_or(&l.statement);
+                CodeSpan::combine(l.variable.location, last.get_location())
+            }
+            Statement::FunctionDeclaration(decl) => Statement::function_location(decl),
+            Statement::Return(expr) => expr.get_location(),
+            Statement::Break(span) => *span,
+            Statement::Continue(span) => *span,
+        }
+    }
+
+    /// Returns the for loop the statement was desugared from, if any.
+    pub fn as_for_loop(&self) -> Option<ForLoop<'_>> {
+        match self {
+            Statement::WhileLoop(while_loop) if while_loop.for_keyword.is_some() => Some(ForLoop {
+                initializer: None,
+                while_loop,
+            }),
+            Statement::Block(Statements {
+                stmts,
+                closing_brace: None,
+            }) => match stmts.as_slice() {
+                [initializer, Statement::WhileLoop(while_loop)]
+                    if while_loop.for_keyword.is_some() =>
+                {
+                    Some(ForLoop {
+                        initializer: Some(initializer),
+                        while_loop,
+                    })
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn function_location(decl: &FunctionDeclaration) -> CodeSpan {
+        CodeSpan::combine(decl.name.location, decl.function.body.get_location())
+    }
+}
+
+impl Extend<Statement> for Statements {
+    fn extend<T: IntoIterator<Item = Statement>>(&mut self, iter: T) {
+        self.stmts.extend(iter);
+    }
 }
 
 impl Debug for Statements {
@@ -33,22 +141,76 @@ impl Debug for Statements {
     }
 }
 
+/// A print statement, whose location spans from the `print` keyword to the
+/// semicolon.
+pub struct Print {
+    pub expr: Expression,
+    pub location: CodeSpan,
+}
+
+/// An assert statement, whose location spans from the `assert` keyword to the
+/// semicolon. The source text of the condition is kept to describe failures.
+pub struct Assert {
+    pub condition: Expression,
+    pub source: String,
+    pub location: CodeSpan,
+}
+
 pub struct Conditional {
     pub condition: Expression,
     pub then_statement: Statement,
     pub else_statement: Option<Statement>,
 }
 
+/// A while loop. For loops are desugared into while loops, so the increment
+/// is evaluated after each iteration of the statement, and the `for` keyword
+/// is kept to display them back as for loops. The else statement only runs if
+/// the loop was not exited with a break.
 pub struct WhileLoop {
     pub condition: Expression,
     pub statement: Statement,
+    pub increment: Option<Expression>,
+    pub else_statement: Option<Statement>,
+    pub for_keyword: Option<CodeSpan>,
 }
 
-pub struct ForLoop {
-    pub initializer: Option<Statement>,
-    pub condition: Option<Expression>,
-    pub increment: Option<Expression>,
-    pub body: Statement,
+/// A for loop, found back from what it is desugared into: a while loop, inside
+/// a block without braces that first runs the initializer if there is one.
+pub struct ForLoop<'a> {
+    pub initializer: Option<&'a Statement>,
+    pub while_loop: &'a WhileLoop,
+}
+
+impl ForLoop<'_> {
+    /// Returns the header of the loop, from the keyword to the closing
+    /// parenthesis. A condition located at the keyword was left out.
+    pub fn header(&self) -> String {
+        let mut header = "for (".to_string();
+        match self.initializer {
+            Some(initializer) => header.push_str(&initializer.to_string()),
+            None => header.push(';'),
+        }
+        let condition = &self.while_loop.condition;
+        if Some(condition.get_location()) != self.while_loop.for_keyword {
+            header.push_str(&format!(" {}", condition));
+        }
+        header.push(';');
+        if let Some(increment) = &self.while_loop.increment {
+            header.push_str(&format!(" {}", increment));
+        }
+        header.push(')');
+        header
+    }
+}
+
+/// A loop running its statement once for each element of a value, such as
+/// each property name of an object. As for while loops, the else statement
+/// only runs if the loop was not exited with a break.
+pub struct ForIn {
+    pub variable: Identifier,
+    pub iterable: Expression,
+    pub statement: Statement,
+    pub else_statement: Option<Statement>,
 }
 
 impl Display for Statements {
@@ -63,14 +225,18 @@ impl Display for Statements {
 impl Display for Statement {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Statement::Print(expr) => write!(f, "print {};", expr),
+            Statement::Print(print) => write!(f, "print {};", print.expr),
+            Statement::Assert(assert) => write!(f, "assert {};", assert.condition),
             Statement::Expression(expr) => write!(f, "{};", expr),
             Statement::VariableDeclaration(v) => write!(f, "{}", v),
             Statement::ClassDeclaration(decl) => write!(f, "{}", decl),
-            Statement::Block(stmts) => write!(f, "{{\n{}}}", stmts),
+            Statement::Block(stmts) => match self.as_for_loop() {
+                Some(for_loop) => write!(f, "{}", for_loop),
+                None => write!(f, "{{\n{}}}", stmts),
+            },
             Statement::Conditional(c) => write!(f, "{}", c),
-            Statement::WhileLoop(l) => write!(f, "while ({}) {}", l.condition, l.statement),
-            Statement::ForLoop(l) => write!(f, "{}", l),
+            Statement::WhileLoop(l) => write!(f, "{}", l),
+            Statement::ForIn(l) => write!(f, "{}", l),
             Statement::FunctionDeclaration(fd) => write!(f, "{}", fd),
             Statement::Return(expr) => match expr {
                 Expression::Literal(l) if l.value == LiteralValue::Nil => {
@@ -78,7 +244,23 @@ impl Display for Statement {
                 }
                 _ => write!(f, "return {};", expr),
             },
+            Statement::Break(_) => write!(f, "break;"),
+            Statement::Continue(_) => write!(f, "continue;"),
+        }
+    }
+}
+
+impl Display for ForIn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "for (var {} in {}) {}",
+            self.variable, self.iterable, self.statement
+        )?;
+        if let Some(else_statement) = &self.else_statement {
+            write!(f, " else {}", else_statement)?;
         }
+        Ok(())
     }
 }
 
@@ -95,22 +277,33 @@ impl Display for Conditional {
     }
 }
 
-impl Display for ForLoop {
+impl Display for WhileLoop {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "for (")?;
-        if let Some(init) = &self.initializer {
-            write!(f, "{}", init)?;
-        } else {
-            write!(f, ";")?;
+        if self.for_keyword.is_some() || self.increment.is_some() {
+            return write!(
+                f,
+                "{}",
+                ForLoop {
+                    initializer: None,
+                    while_loop: self,
+                }
+            );
         }
-        if let Some(cond) = &self.condition {
-            write!(f, " {}", cond)?;
+        write!(f, "while ({}) {}", self.condition, self.statement)?;
+        if let Some(else_statement) = &self.else_statement {
+            write!(f, " else {}", else_statement)?;
         }
-        write!(f, ";")?;
-        if let Some(increment) = &self.increment {
-            write!(f, " {}", increment)?;
+        Ok(())
+    }
+}
+
+impl Display for ForLoop<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.header(), self.while_loop.statement)?;
+        if let Some(else_statement) = &self.while_loop.else_statement {
+            write!(f, " else {}", else_statement)?;
         }
-        write!(f, ") {}", self.body)
+        Ok(())
     }
 }
 
@@ -118,12 +311,13 @@ pub trait StatementVisitor {
     type Return;
 
     fn visit_statement(&mut self, stmt: &Statement) -> Self::Return;
-    fn visit_print(&mut self, expr: &Expression) -> Self::Return;
+    fn visit_print(&mut self, print: &Print) -> Self::Return;
+    fn visit_assert(&mut self, assert: &Assert) -> Self::Return;
     fn visit_variable_declaration(&mut self, decl: &VariableDeclaration) -> Self::Return;
     fn visit_class_declaration(&mut self, decl: &ClassDeclaration) -> Self::Return;
     fn visit_conditional(&mut self, cond: &Conditional) -> Self::Return;
     fn visit_while_loop(&mut self, while_loop: &WhileLoop) -> Self::Return;
-    fn visit_for_loop(&mut self, for_loop: &ForLoop) -> Self::Return;
+    fn visit_for_in(&mut self, for_in: &ForIn) -> Self::Return;
     fn visit_function_declaration(&mut self, fd: &FunctionDeclaration) -> Self::Return;
     fn visit_return(&mut self, expr: &Expression) -> Self::Return;
 }
@@ -1,24 +1,32 @@
 use std::{
+    cell::RefCell,
     collections::HashMap,
+    convert::TryFrom,
     fmt::{Debug, Display, Formatter},
+    hash::{Hash, Hasher},
     rc::Rc,
 };
 
 use crate::{
     ast::{expressions::Identifier, statements::Statements},
     code_span::CodeSpan,
-    eval::Result,
+    eval::{Result, RuntimeError},
 };
 
+/// A value of the language. Cloning a value has reference semantics: strings
+/// are immutable and shared, and a cloned object is the same instance as the
+/// original, so mutating one mutates the other. Use `deep_clone` to get an
+/// independent copy of an object.
 #[derive(Clone, Debug)]
 pub enum ValueType {
     String(Rc<String>),
     Number(f64),
     Boolean(bool),
     Nil,
-    Object(Rc<std::cell::RefCell<Object>>),
-    NativeFunction(NativeFunction, usize),
-    Function(Rc<Function>),
+    Object(Rc<RefCell<Object>>),
+    NativeFunction(Native),
+    Function(Rc<Closure>),
+    BoundMethod(BoundMethod),
     Class(Rc<Class>),
 }
 
@@ -28,6 +36,17 @@ pub struct Value {
     pub value: ValueType,
 }
 
+/// A value that can be used as a map key. Only strings, numbers (except NaN),
+/// booleans and nil are hashable, as the other values are either mutable or
+/// have no meaningful equality.
+#[derive(Clone, Debug)]
+pub enum HashableValue {
+    String(Rc<String>),
+    Number(f64),
+    Boolean(bool),
+    Nil,
+}
+
 #[derive(Eq, PartialEq, Hash, Debug)]
 pub enum Type {
     String,
@@ -48,6 +67,24 @@ pub struct Object {
 
 pub type NativeFunction = fn(Vec<ValueType>, CodeSpan) -> Result<ValueType>;
 
+/// The arity of a native function that takes any number of arguments.
+pub const VARIADIC: usize = usize::MAX;
+
+/// A native function along with the number of arguments it takes, or
+/// `VARIADIC`.
+#[derive(Clone, Copy, Debug)]
+pub struct Native {
+    pub function: NativeFunction,
+    pub arity: usize,
+}
+
+/// A method along with the object it is bound to as `this`.
+#[derive(Clone, Debug)]
+pub struct BoundMethod {
+    pub method: Rc<Closure>,
+    pub this: Rc<RefCell<Object>>,
+}
+
 #[derive(Debug)]
 pub struct Function {
     pub args: Vec<Identifier>,
@@ -55,10 +92,36 @@ pub struct Function {
     pub span: CodeSpan,
 }
 
+/// The variables of a scope, shared between the environment and the closures
+/// declared in it.
+pub type Scope = Rc<RefCell<HashMap<String, ValueType>>>;
+
+/// A function along with the scopes it was declared in, from the outermost
+/// to the innermost, excluding the global one. Variables defined in these
+/// scopes after the declaration, such as the function's own name, are
+/// visible from its body.
+#[derive(Debug)]
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub scopes: Vec<Scope>,
+}
+
 #[derive(Debug)]
 pub struct Class {
     pub name: Identifier,
-    pub methods: HashMap<String, Rc<Function>>,
+    pub superclass: Option<Rc<Class>>,
+    pub methods: HashMap<String, Rc<Closure>>,
+}
+
+impl Class {
+    /// Returns the method with this name, defined by the class itself or
+    /// inherited from its closest superclass defining it.
+    pub fn find_method(&self, name: &str) -> Option<Rc<Closure>> {
+        match self.methods.get(name) {
+            Some(method) => Some(method.clone()),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
 }
 
 impl ValueType {
@@ -69,11 +132,109 @@ impl ValueType {
             ValueType::Boolean(_) => Type::Boolean,
             ValueType::Nil => Type::Nil,
             ValueType::Object(_) => Type::Object,
-            ValueType::NativeFunction(_, _) => Type::NativeFunction,
-            ValueType::Function(_) => Type::Function,
+            ValueType::NativeFunction(_) => Type::NativeFunction,
+            ValueType::Function(_) | ValueType::BoundMethod(_) => Type::Function,
             ValueType::Class(_) => Type::Class,
         }
     }
+
+    /// Returns the same value, sharing objects with the original. This is
+    /// what assignment and argument passing do.
+    pub fn shallow_clone(&self) -> Self {
+        self.clone()
+    }
+
+    /// Returns a copy of the value where every object reachable through
+    /// properties is a new instance, so mutating the copy does not affect the
+    /// original. Objects referenced several times, including through cycles,
+    /// are copied once.
+    pub fn deep_clone(&self) -> Self {
+        self.deep_clone_with(&mut HashMap::new())
+    }
+
+    /// Deep clones the value, reusing the copies already made of objects.
+    fn deep_clone_with(
+        &self,
+        copies: &mut HashMap<*const RefCell<Object>, Rc<RefCell<Object>>>,
+    ) -> Self {
+        match self {
+            ValueType::Object(object) => {
+                if let Some(copy) = copies.get(&Rc::as_ptr(object)) {
+                    return ValueType::Object(copy.clone());
+                }
+                let copy = Rc::new(RefCell::new(Object {
+                    properties: HashMap::new(),
+                    class: object.borrow().class.clone(),
+                }));
+                copies.insert(Rc::as_ptr(object), copy.clone());
+                let properties = object
+                    .borrow()
+                    .properties
+                    .iter()
+                    .map(|(name, value)| {
+                        let value = Value::new(value.value.deep_clone_with(copies), value.location);
+                        (name.clone(), value)
+                    })
+                    .collect();
+                copy.borrow_mut().properties = properties;
+                ValueType::Object(copy)
+            }
+            value => value.clone(),
+        }
+    }
+}
+
+impl TryFrom<Value> for HashableValue {
+    type Error = RuntimeError;
+
+    fn try_from(value: Value) -> Result<Self> {
+        match value.value {
+            ValueType::String(s) => Ok(HashableValue::String(s)),
+            ValueType::Number(n) if !n.is_nan() => Ok(HashableValue::Number(n)),
+            ValueType::Boolean(b) => Ok(HashableValue::Boolean(b)),
+            ValueType::Nil => Ok(HashableValue::Nil),
+            _ => Err(RuntimeError::UnhashableValue(value)),
+        }
+    }
+}
+
+impl From<HashableValue> for ValueType {
+    fn from(value: HashableValue) -> Self {
+        match value {
+            HashableValue::String(s) => ValueType::String(s),
+            HashableValue::Number(n) => ValueType::Number(n),
+            HashableValue::Boolean(b) => ValueType::Boolean(b),
+            HashableValue::Nil => ValueType::Nil,
+        }
+    }
+}
+
+impl PartialEq for HashableValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (HashableValue::String(s1), HashableValue::String(s2)) => s1 == s2,
+            (HashableValue::Number(n1), HashableValue::Number(n2)) => n1 == n2,
+            (HashableValue::Boolean(b1), HashableValue::Boolean(b2)) => b1 == b2,
+            (HashableValue::Nil, HashableValue::Nil) => true,
+            (_, _) => false,
+        }
+    }
+}
+
+impl Eq for HashableValue {}
+
+impl Hash for HashableValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            HashableValue::String(s) => s.hash(state),
+            // 0.0 and -0.0 are equal so they must hash the same
+            HashableValue::Number(n) if *n == 0.0 => 0.0f64.to_bits().hash(state),
+            HashableValue::Number(n) => n.to_bits().hash(state),
+            HashableValue::Boolean(b) => b.hash(state),
+            HashableValue::Nil => (),
+        }
+    }
 }
 
 impl Value {
@@ -83,18 +244,16 @@ impl Value {
 }
 
 impl Debug for Object {
+    /// Debugs the fields like a struct named after the class, sorted by name
+    /// as in `Display`.
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} {{", self.class.name)?;
-        f.write_str("{")?;
-        let mut iter = self.properties.iter();
-        if let Some((name, value)) = iter.next() {
-            write!(f, "{}: {}", name, value)?
-        }
-        for (name, value) in iter {
-            write!(f, ",\n{}: {}", name, value)?
+        let mut properties = self.properties.iter().collect::<Vec<_>>();
+        properties.sort_by_key(|(name, _)| *name);
+        let mut debug = f.debug_struct(&self.class.name.ident);
+        for (name, value) in properties {
+            debug.field(name, &value.value);
         }
-        f.write_str("}")?;
-        Ok(())
+        debug.finish()
     }
 }
 
@@ -109,11 +268,16 @@ impl PartialEq for ValueType {
         match (self, other) {
             (ValueType::String(s1), ValueType::String(s2)) => s1 == s2,
             (ValueType::Nil, ValueType::Nil) => true,
-            (ValueType::Object(_), ValueType::Object(_)) => todo!(),
+            (ValueType::Object(o1), ValueType::Object(o2)) => Rc::ptr_eq(o1, o2),
             (ValueType::Boolean(b1), ValueType::Boolean(b2)) => b1 == b2,
-            (ValueType::NativeFunction(f1, _), ValueType::NativeFunction(f2, _)) => f1 == f2,
+            (ValueType::NativeFunction(f1), ValueType::NativeFunction(f2)) => {
+                std::ptr::fn_addr_eq(f1.function, f2.function)
+            }
             (ValueType::Number(n1), ValueType::Number(n2)) => n1 == n2,
             (ValueType::Function(f1), ValueType::Function(f2)) => Rc::ptr_eq(f1, f2),
+            (ValueType::BoundMethod(m1), ValueType::BoundMethod(m2)) => {
+                Rc::ptr_eq(&m1.method, &m2.method) && Rc::ptr_eq(&m1.this, &m2.this)
+            }
             (_, _) => false,
         }
     }
@@ -129,12 +293,16 @@ impl Display for ValueType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             ValueType::String(s) => write!(f, "{}", s),
+            ValueType::Number(n) if n.is_nan() => write!(f, "NaN"),
+            ValueType::Number(n) if n.is_infinite() => {
+                write!(f, "{}Infinity", if *n < 0.0 { "-" } else { "" })
+            }
             ValueType::Number(n) => write!(f, "{}", n),
             ValueType::Boolean(b) => write!(f, "{}", b),
             ValueType::Nil => write!(f, "nil"),
             ValueType::Object(o) => write!(f, "{}", o.borrow()),
-            ValueType::NativeFunction(_, _) => write!(f, "<native fn>"),
-            ValueType::Function(_) => write!(f, "<function>"),
+            ValueType::NativeFunction(_) => write!(f, "<native fn>"),
+            ValueType::Function(_) | ValueType::BoundMethod(_) => write!(f, "<function>"),
             ValueType::Class(c) => write!(f, "{}", c),
         }
     }
@@ -162,8 +330,179 @@ impl Display for Class {
     }
 }
 
+impl Object {
+    /// Writes the object like `Display`. `parents` holds the objects being
+    /// written around this one; a field referring back to one of them is
+    /// written as `Class {...}` instead of recursing forever.
+    fn write(&self, f: &mut Formatter<'_>, parents: &mut Vec<*const Object>) -> std::fmt::Result {
+        parents.push(self);
+        write!(f, "{} {{", self.class.name)?;
+        let mut properties = self.properties.iter().collect::<Vec<_>>();
+        properties.sort_by_key(|(name, _)| *name);
+        for (i, (name, value)) in properties.into_iter().enumerate() {
+            write!(f, "{} {}: ", if i == 0 { "" } else { "," }, name)?;
+            match &value.value {
+                ValueType::Object(o) if parents.contains(&(o.as_ptr() as *const Object)) => {
+                    write!(f, "{} {{...}}", o.borrow().class.name)?
+                }
+                ValueType::Object(o) => o.borrow().write(f, parents)?,
+                v => write!(f, "{}", v)?,
+            }
+        }
+        if !self.properties.is_empty() {
+            write!(f, " ")?;
+        }
+        parents.pop();
+        write!(f, "}}")
+    }
+}
+
 impl Display for Object {
+    /// Displays the class name followed by the fields, sorted by name so that
+    /// the output is deterministic.
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} instance", self.class.name)
+        self.write(f, &mut Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::location::Location;
+
+    fn key(value: ValueType) -> Result<HashableValue> {
+        let location = Location::start();
+        HashableValue::try_from(Value::new(value, CodeSpan::new(location, location)))
+    }
+
+    #[test]
+    fn number_keys() {
+        let mut map = HashMap::new();
+        map.insert(key(ValueType::Number(1.0)).unwrap(), "one");
+        map.insert(key(ValueType::Number(0.0)).unwrap(), "zero");
+        assert_eq!(map.get(&key(ValueType::Number(1.0)).unwrap()), Some(&"one"));
+        assert_eq!(
+            map.get(&key(ValueType::Number(-0.0)).unwrap()),
+            Some(&"zero")
+        );
+        assert_eq!(map.get(&key(ValueType::Number(2.0)).unwrap()), None);
+    }
+
+    #[test]
+    fn boolean_keys() {
+        let mut map = HashMap::new();
+        map.insert(key(ValueType::Boolean(true)).unwrap(), 1);
+        map.insert(key(ValueType::Boolean(false)).unwrap(), 0);
+        map.insert(key(ValueType::Boolean(true)).unwrap(), 2);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&key(ValueType::Boolean(true)).unwrap()), Some(&2));
+    }
+
+    #[test]
+    fn different_types_are_different_keys() {
+        let mut map = HashMap::new();
+        map.insert(key(ValueType::Nil).unwrap(), 1);
+        map.insert(
+            key(ValueType::String(Rc::new("nil".to_string()))).unwrap(),
+            2,
+        );
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn unhashable_keys() {
+        let location = Location::start();
+        let function = Function {
+            args: vec![],
+            body: Statements::new(),
+            span: CodeSpan::new(location, location),
+        };
+        assert!(matches!(
+            key(ValueType::Function(Rc::new(Closure {
+                function: Rc::new(function),
+                scopes: vec![],
+            }))),
+            Err(RuntimeError::UnhashableValue(_))
+        ));
+        assert!(matches!(
+            key(ValueType::Number(f64::NAN)),
+            Err(RuntimeError::UnhashableValue(_))
+        ));
+    }
+
+    fn object(properties: Vec<(&str, ValueType)>) -> ValueType {
+        let location = CodeSpan::new(Location::start(), Location::start());
+        let class = Rc::new(Class {
+            name: Identifier {
+                ident: "A".to_string(),
+                location,
+                binding: Default::default(),
+            },
+            superclass: None,
+            methods: HashMap::new(),
+        });
+        let properties = properties
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), Value::new(value, location)))
+            .collect();
+        ValueType::Object(Rc::new(RefCell::new(Object { properties, class })))
+    }
+
+    fn set(value: &ValueType, name: &str, property: ValueType) {
+        match value {
+            ValueType::Object(object) => {
+                let mut object = object.borrow_mut();
+                object.properties.get_mut(name).unwrap().value = property;
+            }
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn shallow_and_deep_clones() {
+        let original = object(vec![
+            ("n", ValueType::Number(1.0)),
+            ("child", object(vec![("m", ValueType::Number(2.0))])),
+        ]);
+        let shallow = original.shallow_clone();
+        let deep = original.deep_clone();
+        set(&shallow, "n", ValueType::Number(3.0));
+        assert_eq!(original.to_string(), "A { child: A { m: 2 }, n: 3 }");
+        assert_eq!(deep.to_string(), "A { child: A { m: 2 }, n: 1 }");
+
+        set(&deep, "child", ValueType::Nil);
+        assert_eq!(original.to_string(), "A { child: A { m: 2 }, n: 3 }");
+        assert_eq!(deep.to_string(), "A { child: nil, n: 1 }");
+    }
+
+    #[test]
+    fn object_debug() {
+        let object = object(vec![
+            ("y", ValueType::Boolean(true)),
+            ("x", ValueType::Number(1.0)),
+        ]);
+        match object {
+            ValueType::Object(object) => assert_eq!(
+                format!("{:?}", object.borrow()),
+                "A { x: Number(1.0), y: Boolean(true) }"
+            ),
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn deep_clone_cycle() {
+        let original = object(vec![("me", ValueType::Nil)]);
+        set(&original, "me", original.clone());
+        match original.deep_clone() {
+            ValueType::Object(copy) => {
+                let me = copy.borrow().properties["me"].value.clone();
+                assert!(matches!(me, ValueType::Object(me) if Rc::ptr_eq(&me, &copy)));
+                // Break the cycle so that the objects are freed.
+                copy.borrow_mut().properties.clear();
+            }
+            _ => panic!("not an object"),
+        }
+        set(&original, "me", ValueType::Nil);
     }
 }
@@ -14,6 +14,19 @@ impl CodeSpan {
         CodeSpan { start, end }
     }
 
+    /// A span for code that does not appear in the source, such as the nodes
+    /// produced when desugaring. Lines start at 1, so it can never collide with
+    /// a real span.
+    pub fn synthetic() -> Self {
+        let location = Location::new(0, 0);
+        CodeSpan::new(location, location)
+    }
+
+    /// Returns whether the span was created by `CodeSpan::synthetic`.
+    pub fn is_synthetic(&self) -> bool {
+        *self == CodeSpan::synthetic()
+    }
+
     /// Returns whether the span is contained in one line.
     pub fn is_one_line(&self) -> bool {
         self.start.line == self.end.line
@@ -24,33 +37,65 @@ impl CodeSpan {
         self.start.line..self.end.line
     }
 
-    /// Clones the representation of a token from its source.
+    /// Clones the representation of a token from its source, given as its
+    /// lines. Lines are numbered from 1, and positions count characters.
     pub fn get_repr(&self, source: Vec<&str>) -> String {
+        let line = |n: usize| source[n - 1].chars();
         if self.is_one_line() {
-            source[self.start.line][self.start.char..self.end.char].to_string()
+            line(self.start.line)
+                .skip(self.start.char)
+                .take(self.end.char - self.start.char)
+                .collect()
         } else {
-            let mut s = String::from(&source[self.start.line][self.start.char..]);
-            let mut line = self.start.line + 1;
-            while line < self.end.line {
-                s.push_str(source[line]);
-                line += 1;
+            let mut s = line(self.start.line)
+                .skip(self.start.char)
+                .collect::<String>();
+            for n in self.start.line + 1..self.end.line {
+                s.push('\n');
+                s.extend(line(n));
             }
-            s.push_str(&source[line][..self.end.char]);
+            s.push('\n');
+            s.extend(line(self.end.line).take(self.end.char));
             s
         }
     }
 
+    /// Returns the span from the start of `left` to the end of `right`. A
+    /// synthetic span has no position, so combining it with another span
+    /// returns the other span.
     pub fn combine(left: CodeSpan, right: CodeSpan) -> Self {
+        if left.is_synthetic() {
+            return right;
+        }
+        if right.is_synthetic() {
+            return left;
+        }
         CodeSpan {
             start: left.start,
             end: right.end,
         }
     }
+
+    /// Returns the span from the earliest start to the latest end of the
+    /// spans, whatever their order, or `None` if there are none. Synthetic
+    /// spans are ignored.
+    pub fn enclosing(spans: impl IntoIterator<Item = CodeSpan>) -> Option<CodeSpan> {
+        let key = |location: &Location| (location.line, location.char);
+        spans
+            .into_iter()
+            .filter(|span| !span.is_synthetic())
+            .reduce(|enclosing, span| CodeSpan {
+                start: std::cmp::min_by_key(enclosing.start, span.start, key),
+                end: std::cmp::max_by_key(enclosing.end, span.end, key),
+            })
+    }
 }
 
 impl Debug for CodeSpan {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        if self.start != self.end {
+        if self.is_synthetic() {
+            write!(f, "<generated>")
+        } else if self.start != self.end {
             write!(f, "{}-{}", self.start, self.end)
         } else {
             write!(f, "{}", self.start)
@@ -63,3 +108,44 @@ impl Display for CodeSpan {
         <Self as Debug>::fmt(self, f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_repr() {
+        let source = vec!["var é = 1;", "print é +", "  2;"];
+        let span = CodeSpan::new(Location::new(1, 4), Location::new(1, 5));
+        assert_eq!(span.get_repr(source.clone()), "é");
+        let span = CodeSpan::new(Location::new(2, 6), Location::new(3, 3));
+        assert_eq!(span.get_repr(source), "é +\n  2");
+    }
+
+    #[test]
+    fn synthetic() {
+        assert!(CodeSpan::synthetic().is_synthetic());
+        assert_eq!(CodeSpan::synthetic().to_string(), "<generated>");
+        let start = CodeSpan::new(Location::start(), Location::start());
+        assert!(!start.is_synthetic());
+        assert_eq!(start.to_string(), "[1,0]");
+        assert_eq!(CodeSpan::combine(CodeSpan::synthetic(), start), start);
+        assert_eq!(CodeSpan::combine(start, CodeSpan::synthetic()), start);
+    }
+
+    #[test]
+    fn enclosing() {
+        let span = |start, end| CodeSpan::new(Location::new(1, start), Location::new(1, end));
+        assert_eq!(CodeSpan::enclosing([]), None);
+        assert_eq!(CodeSpan::enclosing([span(2, 4)]), Some(span(2, 4)));
+        assert_eq!(
+            CodeSpan::enclosing([span(5, 6), span(0, 1), span(2, 9)]),
+            Some(span(0, 9))
+        );
+        let multiline = CodeSpan::new(Location::new(2, 0), Location::new(3, 1));
+        assert_eq!(
+            CodeSpan::enclosing([multiline, span(4, 8), CodeSpan::synthetic()]),
+            Some(CodeSpan::new(Location::new(1, 4), Location::new(3, 1)))
+        );
+    }
+}
@@ -1,20 +1,161 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    time::SystemTime,
+};
+
 use crate::{
-    ast::types::{NativeFunction, ValueType},
+    ast::{
+        expressions::Identifier,
+        types::{Class, NativeFunction, Object, Value, ValueType, VARIADIC},
+    },
     code_span::CodeSpan,
-    eval::Result,
+    eval::{json_to_value, value_to_json, Result, RuntimeError, Type},
 };
 
 fn clock(_: Vec<ValueType>, _: CodeSpan) -> Result<ValueType> {
     Ok(ValueType::Number(
         std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
-            .expect("Wut")
+            .expect("the system time is after the Unix epoch")
             .as_secs_f64(),
     ))
 }
 
+fn now(_: Vec<ValueType>, span: CodeSpan) -> Result<ValueType> {
+    Ok(date(SystemTime::now(), span))
+}
+
+/// Builds a `Date` object holding the UTC calendar components of a time.
+fn date(time: SystemTime, span: CodeSpan) -> ValueType {
+    let seconds = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("the system time is after the Unix epoch")
+        .as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let components = [
+        ("year", year),
+        ("month", month),
+        ("day", day),
+        ("hour", (seconds % 86400 / 3600) as i64),
+        ("minute", (seconds % 3600 / 60) as i64),
+        ("second", (seconds % 60) as i64),
+    ];
+    let properties = components
+        .into_iter()
+        .map(|(name, n)| {
+            (
+                name.to_string(),
+                Value::new(ValueType::Number(n as f64), span),
+            )
+        })
+        .collect();
+    let class = Rc::new(Class {
+        name: Identifier {
+            ident: "Date".to_string(),
+            location: span,
+            binding: Default::default(),
+        },
+        superclass: None,
+        methods: HashMap::new(),
+    });
+    ValueType::Object(Rc::new(RefCell::new(Object { properties, class })))
+}
+
+/// Converts a number of days since the Unix epoch to a (year, month, day)
+/// date of the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn pad_left(args: Vec<ValueType>, span: CodeSpan) -> Result<ValueType> {
+    let (text, width) = pad_arguments(args, span)?;
+    Ok(ValueType::String(Rc::new(format!("{:>width$}", text))))
+}
+
+fn pad_right(args: Vec<ValueType>, span: CodeSpan) -> Result<ValueType> {
+    let (text, width) = pad_arguments(args, span)?;
+    Ok(ValueType::String(Rc::new(format!("{:<width$}", text))))
+}
+
+/// Returns the concatenation of what `print` would write for each argument.
+fn sprint(args: Vec<ValueType>, _: CodeSpan) -> Result<ValueType> {
+    let text = args.iter().map(ValueType::to_string).collect::<String>();
+    Ok(ValueType::String(Rc::new(text)))
+}
+
+fn to_json(args: Vec<ValueType>, span: CodeSpan) -> Result<ValueType> {
+    let [value] = <[ValueType; 1]>::try_from(args)
+        .expect("native function called with incorrect number of arguments");
+    let json = value_to_json(&Value::new(value, span))?;
+    Ok(ValueType::String(Rc::new(json)))
+}
+
+fn from_json(args: Vec<ValueType>, span: CodeSpan) -> Result<ValueType> {
+    let [json] = <[ValueType; 1]>::try_from(args)
+        .expect("native function called with incorrect number of arguments");
+    match json {
+        ValueType::String(json) => json_to_value(&json, span),
+        v => Err(RuntimeError::MismatchedTypes(
+            span,
+            v.as_type(),
+            HashSet::from([Type::String]),
+        )),
+    }
+}
+
+/// The widest string `pad_left` and `pad_right` can pad to, so that a huge
+/// width cannot allocate unbounded memory.
+const MAX_PAD_WIDTH: usize = 4096;
+
+/// Returns the string form of the value to pad and the width to pad it to.
+/// Values already wider than the width are left as they are.
+fn pad_arguments(args: Vec<ValueType>, span: CodeSpan) -> Result<(String, usize)> {
+    let [value, width] = <[ValueType; 2]>::try_from(args)
+        .expect("native function called with incorrect number of arguments");
+    match width {
+        ValueType::Number(n) if n > MAX_PAD_WIDTH as f64 => Err(RuntimeError::InvalidArgument(
+            span,
+            format!("width must be at most {}, got {}", MAX_PAD_WIDTH, n),
+        )),
+        ValueType::Number(n) if n >= 0.0 && n.fract() == 0.0 => Ok((value.to_string(), n as usize)),
+        ValueType::Number(n) => Err(RuntimeError::InvalidArgument(
+            span,
+            format!("width must be a non-negative integer, got {}", n),
+        )),
+        v => Err(RuntimeError::MismatchedTypes(
+            span,
+            v.as_type(),
+            HashSet::from([Type::Number]),
+        )),
+    }
+}
+
 pub fn prelude() -> Vec<(&'static str, NativeFunction, usize)> {
-    vec![("clock", clock, 0)]
+    vec![
+        ("clock", clock, 0),
+        ("now", now, 0),
+        ("pad_left", pad_left, 2),
+        ("pad_right", pad_right, 2),
+        ("sprint", sprint, VARIADIC),
+        ("to_json", to_json, 1),
+        ("from_json", from_json, 1),
+    ]
 }
 
 #[cfg(test)]
@@ -34,5 +175,35 @@ pub fn test_prelude() -> Vec<(&'static str, NativeFunction, usize)> {
             )),
         }
     }
-    vec![("hello", hello, 1)]
+    let mut prelude = prelude();
+    prelude.push(("hello", hello, 1));
+    prelude
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::location::Location;
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(59), (1970, 3, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn date_components() {
+        // 2021-03-04T05:06:07Z
+        let time = UNIX_EPOCH + Duration::from_secs(1614834367);
+        let span = CodeSpan::new(Location::start(), Location::start());
+        let value = date(time, span);
+        assert_eq!(
+            value.to_string(),
+            "Date { day: 4, hour: 5, minute: 6, month: 3, second: 7, year: 2021 }"
+        );
+    }
 }
@@ -0,0 +1,239 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::{
+        statements::StatementVisitor,
+        types::{BoundMethod, Class, Closure, Native, Object, ValueType},
+    },
+    code_span::CodeSpan,
+    eval::{self, Evaluator, RuntimeError},
+};
+
+/// A value that can be called like a function.
+pub trait Callable {
+    /// The number of arguments the callable takes.
+    fn arity(&self) -> usize;
+
+    /// Calls the callable. The number of arguments must match the arity.
+    fn call(
+        &self,
+        evaluator: &mut Evaluator,
+        arguments: Vec<ValueType>,
+        location: CodeSpan,
+    ) -> eval::Result<ValueType>;
+}
+
+/// Returns the value as a callable, if it is one.
+pub(super) fn as_callable(value: &ValueType) -> Option<&dyn Callable> {
+    match value {
+        ValueType::NativeFunction(native) => Some(native),
+        ValueType::Function(function) => Some(function.as_ref()),
+        ValueType::BoundMethod(method) => Some(method),
+        ValueType::Class(class) => Some(class),
+        _ => None,
+    }
+}
+
+impl Callable for Native {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(
+        &self,
+        evaluator: &mut Evaluator,
+        arguments: Vec<ValueType>,
+        location: CodeSpan,
+    ) -> eval::Result<ValueType> {
+        let result = (self.function)(arguments, location)?;
+        evaluator.allocate(allocated_size(&result), location)?;
+        Ok(result)
+    }
+}
+
+/// Returns the number of bytes allocated for a value returned by a native
+/// function. Natives always return new strings and objects, so all of it is
+/// counted.
+fn allocated_size(value: &ValueType) -> usize {
+    match value {
+        ValueType::String(s) => s.len(),
+        ValueType::Object(object) => {
+            let properties = &object.borrow().properties;
+            std::mem::size_of::<Object>()
+                + properties
+                    .values()
+                    .map(|property| allocated_size(&property.value))
+                    .sum::<usize>()
+        }
+        _ => 0,
+    }
+}
+
+impl Callable for Closure {
+    fn arity(&self) -> usize {
+        self.function.args.len()
+    }
+
+    fn call(
+        &self,
+        evaluator: &mut Evaluator,
+        arguments: Vec<ValueType>,
+        _: CodeSpan,
+    ) -> eval::Result<ValueType> {
+        call_function(evaluator, self, None, arguments)
+    }
+}
+
+impl Callable for BoundMethod {
+    fn arity(&self) -> usize {
+        self.method.function.args.len()
+    }
+
+    fn call(
+        &self,
+        evaluator: &mut Evaluator,
+        arguments: Vec<ValueType>,
+        _: CodeSpan,
+    ) -> eval::Result<ValueType> {
+        call_function(evaluator, &self.method, Some(self.this.clone()), arguments)
+    }
+}
+
+impl Callable for Rc<Class> {
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn call(
+        &self,
+        evaluator: &mut Evaluator,
+        _: Vec<ValueType>,
+        location: CodeSpan,
+    ) -> eval::Result<ValueType> {
+        evaluator.allocate(std::mem::size_of::<Object>(), location)?;
+        Ok(ValueType::Object(Rc::new(RefCell::new(Object {
+            properties: HashMap::new(),
+            class: self.clone(),
+        }))))
+    }
+}
+
+/// Calls a function in the scopes it was declared in, binding `this` in its
+/// environment if it is a method. The caller's scopes are restored afterwards.
+fn call_function(
+    evaluator: &mut Evaluator,
+    closure: &Closure,
+    this: Option<Rc<RefCell<Object>>>,
+    arguments: Vec<ValueType>,
+) -> eval::Result<ValueType> {
+    let caller_scopes = evaluator.env.replace(closure.scopes.clone());
+    let result = run_function(evaluator, closure, this, arguments);
+    evaluator.env.replace(caller_scopes);
+    result
+}
+
+fn run_function(
+    evaluator: &mut Evaluator,
+    closure: &Closure,
+    this: Option<Rc<RefCell<Object>>>,
+    arguments: Vec<ValueType>,
+) -> eval::Result<ValueType> {
+    let function = &closure.function;
+    let mut scope = evaluator.scope();
+    if let Some(this) = this {
+        scope
+            .env
+            .define("this".to_string(), ValueType::Object(this));
+    }
+    for (arg, value) in function.args.iter().zip(arguments) {
+        scope.env.define(arg.ident.clone(), value);
+    }
+    for stmt in &function.body.stmts {
+        match scope.visit_statement(stmt) {
+            Ok(()) => (),
+            Err(RuntimeError::Return(value)) => return Ok(value.value),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(ValueType::Nil)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::expressions::ExpressionVisitor,
+        eval::builtins::test_prelude,
+        location::Location,
+        parsing::{parse, parse_expression},
+        scanning::TokenStream,
+    };
+
+    fn evaluator() -> Evaluator {
+        let code = "fun add(a, b) { return a + b; } \
+            class Point { norm1() { return this.x + this.y; } } \
+            var p = Point(); p.x = 1; p.y = 2;";
+        let (mut evaluator, _) = Evaluator::capturing();
+        evaluator.register_prelude(test_prelude());
+        for stmt in &parse(&mut TokenStream::new(code)).unwrap().stmts {
+            evaluator.visit_statement(stmt).unwrap();
+        }
+        evaluator
+    }
+
+    fn value(evaluator: &mut Evaluator, code: &str) -> ValueType {
+        let expr = parse_expression(&mut TokenStream::new(code)).unwrap();
+        evaluator.visit_expression(&expr).unwrap().value
+    }
+
+    fn call(evaluator: &mut Evaluator, callee: &str, arguments: Vec<ValueType>) -> ValueType {
+        let callee = value(evaluator, callee);
+        let callable = as_callable(&callee).unwrap();
+        assert_eq!(callable.arity(), arguments.len());
+        let location = CodeSpan::new(Location::start(), Location::start());
+        callable.call(evaluator, arguments, location).unwrap()
+    }
+
+    #[test]
+    fn native() {
+        let mut evaluator = evaluator();
+        let name = ValueType::String(Rc::new("you".to_string()));
+        assert_eq!(
+            call(&mut evaluator, "hello", vec![name]).to_string(),
+            "Hello, you"
+        );
+    }
+
+    #[test]
+    fn function() {
+        let mut evaluator = evaluator();
+        let arguments = vec![ValueType::Number(1.0), ValueType::Number(2.0)];
+        assert_eq!(
+            call(&mut evaluator, "add", arguments),
+            ValueType::Number(3.0)
+        );
+    }
+
+    #[test]
+    fn bound_method() {
+        let mut evaluator = evaluator();
+        assert_eq!(
+            call(&mut evaluator, "p.norm1", vec![]),
+            ValueType::Number(3.0)
+        );
+    }
+
+    #[test]
+    fn class() {
+        let mut evaluator = evaluator();
+        let object = call(&mut evaluator, "Point", vec![]);
+        assert_eq!(object.to_string(), "Point {}");
+    }
+
+    #[test]
+    fn not_callable() {
+        let mut evaluator = evaluator();
+        assert!(as_callable(&value(&mut evaluator, "p")).is_none());
+        assert!(as_callable(&ValueType::Nil).is_none());
+    }
+}
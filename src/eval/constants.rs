@@ -0,0 +1,180 @@
+use crate::{
+    ast::{
+        expressions::{BinaryOperator, Expression, Literal},
+        types::{Value, ValueType},
+        LiteralValue,
+    },
+    eval::{
+        expressions::{binary_operation, literal_value, unary_operation},
+        is_truthy, Options,
+    },
+};
+
+/// Evaluates an expression that does not depend on the environment and has no
+/// side effects, such as `2 * (3 + 4)`. Returns `None` if the expression
+/// refers to variables, calls functions, accesses properties or errors.
+/// Logical operators short-circuit, so `false and x` is the constant `false`.
+pub fn eval_constant(expr: &Expression) -> Option<ValueType> {
+    match expr {
+        Expression::Literal(literal) => Some(literal_value(literal)),
+        Expression::UnaryOperation(unary) => {
+            unary_operation(unary, eval_constant(&unary.expr)?).ok()
+        }
+        Expression::BinaryOperation(binary) => {
+            let left = eval_constant(&binary.left)?;
+            match binary.operator {
+                BinaryOperator::Conjunction if !is_truthy(&left) => Some(left),
+                BinaryOperator::Disjunction if is_truthy(&left) => Some(left),
+                BinaryOperator::Conjunction | BinaryOperator::Disjunction => {
+                    eval_constant(&binary.right)
+                }
+                operator => binary_operation(
+                    operator,
+                    Value::new(left, binary.left.get_location()),
+                    Value::new(eval_constant(&binary.right)?, binary.right.get_location()),
+                    // Comparisons of different types might error at runtime,
+                    // so they are left for the evaluator to decide.
+                    &Options {
+                        strict_equality: true,
+                        ..Options::default()
+                    },
+                )
+                .ok(),
+            }
+        }
+        Expression::Identifier(_)
+        | Expression::Assignment(_)
+        | Expression::Call(_)
+        | Expression::Get(_)
+        | Expression::Set(_)
+        | Expression::This(_)
+        | Expression::Super(_) => None,
+    }
+}
+
+/// Replaces constant sub-expressions by their value. A logical operator whose
+/// left operand is constant is replaced by the operand that would be evaluated,
+/// so `false and f()` folds to `false` and `true and x` to `x`.
+pub fn fold_constants(expr: Expression) -> Expression {
+    let expr = match expr {
+        Expression::UnaryOperation(mut unary) => {
+            unary.expr = Box::new(fold_constants(*unary.expr));
+            Expression::UnaryOperation(unary)
+        }
+        Expression::BinaryOperation(mut binary) => {
+            binary.left = Box::new(fold_constants(*binary.left));
+            binary.right = Box::new(fold_constants(*binary.right));
+            match (binary.operator, eval_constant(&binary.left)) {
+                (BinaryOperator::Conjunction, Some(left)) if is_truthy(&left) => *binary.right,
+                (BinaryOperator::Disjunction, Some(left)) if !is_truthy(&left) => *binary.right,
+                (BinaryOperator::Conjunction | BinaryOperator::Disjunction, Some(_)) => {
+                    *binary.left
+                }
+                _ => Expression::BinaryOperation(binary),
+            }
+        }
+        Expression::Assignment(mut assignment) => {
+            assignment.expr = Box::new(fold_constants(*assignment.expr));
+            Expression::Assignment(assignment)
+        }
+        Expression::Call(mut call) => {
+            call.callee = Box::new(fold_constants(*call.callee));
+            call.arguments = call.arguments.into_iter().map(fold_constants).collect();
+            Expression::Call(call)
+        }
+        Expression::Get(mut get) => {
+            get.object = Box::new(fold_constants(*get.object));
+            Expression::Get(get)
+        }
+        Expression::Set(mut set) => {
+            set.object = Box::new(fold_constants(*set.object));
+            set.value = Box::new(fold_constants(*set.value));
+            Expression::Set(set)
+        }
+        expr @ (Expression::Literal(_)
+        | Expression::Identifier(_)
+        | Expression::This(_)
+        | Expression::Super(_)) => expr,
+    };
+    match eval_constant(&expr).and_then(literal_value_of) {
+        Some(value) => Expression::Literal(Literal::new(value, expr.get_location())),
+        None => expr,
+    }
+}
+
+fn literal_value_of(value: ValueType) -> Option<LiteralValue> {
+    match value {
+        ValueType::String(s) => Some(LiteralValue::StringLiteral(s)),
+        ValueType::Number(n) => Some(LiteralValue::NumberLiteral(n)),
+        ValueType::Boolean(true) => Some(LiteralValue::True),
+        ValueType::Boolean(false) => Some(LiteralValue::False),
+        ValueType::Nil => Some(LiteralValue::Nil),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{parsing::parse_expression, scanning::TokenStream};
+
+    fn eval(code: &str) -> Option<ValueType> {
+        eval_constant(&parse_expression(&mut TokenStream::new(code)).unwrap())
+    }
+
+    #[test]
+    fn constant_expressions() {
+        assert_eq!(eval("2 * (3 + 4)"), Some(ValueType::Number(14.0)));
+        assert_eq!(eval("!(1 < 2)"), Some(ValueType::Boolean(false)));
+        assert_eq!(
+            eval("\"a\" + \"b\""),
+            Some(ValueType::String(Rc::new("ab".to_string())))
+        );
+        assert_eq!(eval("nil or 3"), Some(ValueType::Number(3.0)));
+        assert_eq!(eval("false and x"), Some(ValueType::Boolean(false)));
+    }
+
+    #[test]
+    fn non_constant_expressions() {
+        assert_eq!(eval("x + 1"), None);
+        assert_eq!(eval("f()"), None);
+        assert_eq!(eval("a.b"), None);
+        assert_eq!(eval("true and x"), None);
+    }
+
+    fn fold(code: &str) -> String {
+        fold_constants(parse_expression(&mut TokenStream::new(code)).unwrap()).to_string()
+    }
+
+    #[test]
+    fn folding() {
+        assert_eq!(fold("x + 2 * (3 + 4)"), "x + 14");
+        assert_eq!(fold("f(1 + 1, -(2))"), "f(2, -2)");
+        assert_eq!(fold("1 / 0"), "1 / 0");
+    }
+
+    #[test]
+    fn folding_logical_operators() {
+        assert_eq!(fold("true and x"), "x");
+        assert_eq!(fold("false and x"), "false");
+        assert_eq!(fold("true or x"), "true");
+        assert_eq!(fold("nil or x"), "x");
+        assert_eq!(fold("x and true"), "x and true");
+    }
+
+    #[test]
+    fn folding_drops_discarded_branches() {
+        assert_eq!(fold("false and f()"), "false");
+        assert_eq!(fold("1 == 1 or (a = 2)"), "true");
+        assert_eq!(fold("!true and 1 / 0"), "false");
+    }
+
+    #[test]
+    fn erroring_expressions() {
+        assert_eq!(eval("1 / 0"), None);
+        assert_eq!(eval("-\"a\""), None);
+        assert_eq!(eval("1 == \"1\""), None);
+    }
+}
@@ -1,13 +1,18 @@
-use std::collections::HashMap;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
 
 use crate::{
-    ast::types::{Value, ValueType},
+    ast::types::{Scope, Value, ValueType},
     eval::runtime_error::RuntimeError,
 };
 
 pub struct Environment {
     global: HashMap<String, ValueType>,
-    stack: Vec<HashMap<String, ValueType>>,
+    stack: Vec<Scope>,
 }
 
 impl Environment {
@@ -19,40 +24,222 @@ impl Environment {
     }
 
     pub fn push_env(&mut self) {
-        self.stack.push(HashMap::new());
+        self.stack.push(Rc::new(RefCell::new(HashMap::new())));
     }
 
     pub fn pop_env(&mut self) {
         self.stack.pop();
     }
 
+    /// Returns the number of scopes pushed on top of the global one.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Returns whether no scope is pushed, so that code runs at the top level.
+    pub fn is_global_scope(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// Pushes a new scope that is popped when the returned guard is dropped.
+    pub fn scope(&mut self) -> ScopeGuard<'_, Self> {
+        ScopeGuard::new(self)
+    }
+
+    /// Returns the scopes pushed on top of the global one, for a closure to
+    /// capture.
+    pub fn capture(&self) -> Vec<Scope> {
+        self.stack.clone()
+    }
+
+    /// Replaces the scopes pushed on top of the global one, returning the
+    /// previous ones so that they can be restored.
+    pub fn replace(&mut self, scopes: Vec<Scope>) -> Vec<Scope> {
+        std::mem::replace(&mut self.stack, scopes)
+    }
+
+    /// Defines a variable in the innermost scope.
     pub fn define(&mut self, identifier: String, value: ValueType) {
-        let map = self.stack.first_mut().unwrap_or(&mut self.global);
-        map.insert(identifier, value);
+        match self.stack.last() {
+            Some(scope) => scope.borrow_mut().insert(identifier, value),
+            None => self.global.insert(identifier, value),
+        };
     }
 
     pub fn assign(&mut self, ident: String, value: Value) -> super::Result<()> {
-        for env in self.stack.iter_mut().rev() {
-            if env.contains_key(&ident) {
-                env.insert(ident, value.value);
+        for env in self.stack.iter().rev() {
+            if let Some(slot) = env.borrow_mut().get_mut(&ident) {
+                *slot = value.value;
                 return Ok(());
             }
         }
 
-        if self.global.contains_key(&ident) {
-            self.global.insert(ident, value.value);
+        if let Some(slot) = self.global.get_mut(&ident) {
+            *slot = value.value;
             Ok(())
         } else {
             Err(RuntimeError::UnboundName(value.location, ident))
         }
     }
 
-    pub fn get(&self, identifier: &str) -> Option<&ValueType> {
-        for map in &self.stack {
-            if let Some(value) = map.get(identifier) {
-                return Some(value);
+    /// Returns the value of the innermost variable with this name.
+    pub fn get(&self, identifier: &str) -> Option<ValueType> {
+        for map in self.stack.iter().rev() {
+            if let Some(value) = map.borrow().get(identifier) {
+                return Some(value.clone());
+            }
+        }
+        self.global.get(identifier).cloned()
+    }
+
+    /// Returns the value of a variable in the scope `depth` scopes out from
+    /// the innermost one.
+    pub fn get_at(&self, depth: usize, identifier: &str) -> Option<ValueType> {
+        let index = self.stack.len().checked_sub(depth + 1)?;
+        self.stack[index].borrow().get(identifier).cloned()
+    }
+
+    /// Returns the value of a global variable.
+    pub fn get_global(&self, identifier: &str) -> Option<ValueType> {
+        self.global.get(identifier).cloned()
+    }
+
+    /// Assigns a variable in the scope `depth` scopes out from the innermost
+    /// one.
+    pub fn assign_at(&mut self, depth: usize, ident: String, value: Value) -> super::Result<()> {
+        if let Some(index) = self.stack.len().checked_sub(depth + 1) {
+            if let Some(slot) = self.stack[index].borrow_mut().get_mut(&ident) {
+                *slot = value.value;
+                return Ok(());
+            }
+        }
+        Err(RuntimeError::UnboundName(value.location, ident))
+    }
+
+    /// Assigns a global variable.
+    pub fn assign_global(&mut self, ident: String, value: Value) -> super::Result<()> {
+        match self.global.get_mut(&ident) {
+            Some(slot) => {
+                *slot = value.value;
+                Ok(())
+            }
+            None => Err(RuntimeError::UnboundName(value.location, ident)),
+        }
+    }
+
+    /// Returns the global variables and their values, sorted by name.
+    pub fn dump(&self) -> Vec<(String, ValueType)> {
+        let mut globals = self
+            .global
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        globals.sort_by(|(a, _), (b, _)| a.cmp(b));
+        globals
+    }
+}
+
+impl AsMut<Environment> for Environment {
+    fn as_mut(&mut self) -> &mut Environment {
+        self
+    }
+}
+
+/// A scope pushed on an environment, which is popped when the guard is
+/// dropped, even if the code using it returns early. The guard gives access
+/// to whatever owns the environment, typically the `Evaluator`.
+pub struct ScopeGuard<'a, T: AsMut<Environment>> {
+    owner: &'a mut T,
+}
+
+impl<'a, T: AsMut<Environment>> ScopeGuard<'a, T> {
+    pub fn new(owner: &'a mut T) -> Self {
+        owner.as_mut().push_env();
+        ScopeGuard { owner }
+    }
+}
+
+impl<T: AsMut<Environment>> Deref for ScopeGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.owner
+    }
+}
+
+impl<T: AsMut<Environment>> DerefMut for ScopeGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.owner
+    }
+}
+
+impl<T: AsMut<Environment>> Drop for ScopeGuard<'_, T> {
+    fn drop(&mut self) {
+        self.owner.as_mut().pop_env();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn define_and_fail(env: &mut Environment) -> Result<(), ()> {
+        let mut scope = env.scope();
+        scope.define("a".to_string(), ValueType::Number(1.0));
+        Err(())?;
+        scope.define("b".to_string(), ValueType::Number(2.0));
+        Ok(())
+    }
+
+    #[test]
+    fn scope_is_popped_on_early_return() {
+        let mut env = Environment::new();
+        assert!(define_and_fail(&mut env).is_err());
+        assert!(env.stack.is_empty());
+        assert!(env.get("a").is_none());
+    }
+
+    #[test]
+    fn depth() {
+        let mut env = Environment::new();
+        assert!(env.is_global_scope());
+        assert_eq!(env.depth(), 0);
+        env.push_env();
+        env.push_env();
+        assert!(!env.is_global_scope());
+        assert_eq!(env.depth(), 2);
+        env.pop_env();
+        assert_eq!(env.depth(), 1);
+        env.pop_env();
+        assert!(env.is_global_scope());
+    }
+
+    #[test]
+    fn shadowing() {
+        let mut env = Environment::new();
+        env.define("a".to_string(), ValueType::Number(0.0));
+        for depth in 1..=3 {
+            env.push_env();
+            env.define("a".to_string(), ValueType::Number(depth as f64));
+            assert_eq!(env.get("a"), Some(ValueType::Number(depth as f64)));
+        }
+        for depth in (0..3).rev() {
+            env.pop_env();
+            assert_eq!(env.get("a"), Some(ValueType::Number(depth as f64)));
+        }
+    }
+
+    #[test]
+    fn nested_scopes() {
+        let mut env = Environment::new();
+        {
+            let mut outer = env.scope();
+            {
+                let inner = outer.scope();
+                assert_eq!(inner.stack.len(), 2);
             }
+            assert_eq!(outer.stack.len(), 1);
         }
-        self.global.get(identifier)
+        assert!(env.stack.is_empty());
     }
 }
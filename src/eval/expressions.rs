@@ -1,97 +1,59 @@
-use std::{
-    collections::{HashMap, HashSet},
-    rc::Rc,
-};
+use std::{collections::HashSet, rc::Rc};
 
 use crate::{
     ast::{
         expressions::{
-            Assignment, Binary, BinaryOperator, Call, Expression, ExpressionVisitor, Get,
-            Identifier, Literal, Set, Unary, UnaryOperator,
+            Assignment, Binary, BinaryOperator, Binding, Call, Expression, ExpressionVisitor, Get,
+            Identifier, Literal, Set, Super, Unary, UnaryOperator,
         },
-        types::{Object, Type, Value, ValueType},
+        types::{BoundMethod, Type, Value, ValueType},
         LiteralValue,
     },
     code_span::CodeSpan,
     eval::{
         self,
         runtime_error::RuntimeError::{self, DivisionByZero, MismatchedTypes},
-        Evaluator,
+        Evaluator, Options, Warning,
     },
-    StatementVisitor,
 };
 
 impl ExpressionVisitor for Evaluator {
     type Return = eval::Result<Value>;
 
     fn visit_literal(&mut self, literal: &Literal) -> Self::Return {
-        let value = (*literal).clone();
-        let value_type = match value.value {
-            LiteralValue::StringLiteral(s) => ValueType::String(Rc::new(s)),
-            LiteralValue::NumberLiteral(n) => ValueType::Number(n),
-            LiteralValue::True => ValueType::Boolean(true),
-            LiteralValue::False => ValueType::Boolean(false),
-            LiteralValue::Nil => ValueType::Nil,
-        };
-        Ok(Value::new(value_type, literal.location))
+        Ok(Value::new(literal_value(literal), literal.location))
     }
 
     fn visit_unary(&mut self, unary: &Unary) -> Self::Return {
         let operand = self.visit_expression(unary.expr.as_ref())?;
-        let value_type = match (unary.op, operand.value) {
-            (UnaryOperator::Minus, ValueType::Number(n)) => Ok(ValueType::Number(-n)),
-            (UnaryOperator::Minus, v) => Err(MismatchedTypes(
-                unary.location,
-                Type::from(&v),
-                HashSet::from([Type::Number]),
-            )),
-            (UnaryOperator::Not, val) => Ok(ValueType::Boolean(!eval::is_truthy(&val))),
+        let value_type = match unary.op {
+            UnaryOperator::Not => ValueType::Boolean(!self.truthiness(&operand)?),
+            UnaryOperator::Minus | UnaryOperator::Plus => unary_operation(unary, operand.value)?,
         };
-        Ok(Value::new(value_type?, unary.location))
+        Ok(Value::new(value_type, unary.location))
     }
 
     fn visit_binary(&mut self, binary: &Binary) -> Self::Return {
         let left = self.visit_expression(binary.left.as_ref())?;
         let value_type = match binary.operator {
-            BinaryOperator::Addition => {
-                addition(left, self.visit_expression(binary.right.as_ref())?)
-            }
-            BinaryOperator::Subtraction => {
-                subtraction(left, self.visit_expression(binary.right.as_ref())?)
-            }
-            BinaryOperator::Multiplication => {
-                multiplication(left, self.visit_expression(binary.right.as_ref())?)
-            }
-            BinaryOperator::Division => {
-                division(left, self.visit_expression(binary.right.as_ref())?)
-            }
-            BinaryOperator::StrictInferiority => {
-                strict_inferiority(left, self.visit_expression(binary.right.as_ref())?)
-            }
-            BinaryOperator::Inferiority => {
-                inferiority(left, self.visit_expression(binary.right.as_ref())?)
-            }
-            BinaryOperator::StrictSuperiority => {
-                strict_superiority(left, self.visit_expression(binary.right.as_ref())?)
-            }
-            BinaryOperator::Superiority => {
-                superiority(left, self.visit_expression(binary.right.as_ref())?)
-            }
-            BinaryOperator::Equality => {
-                equality(left, self.visit_expression(binary.right.as_ref())?)
-            }
-            BinaryOperator::Inequality => {
-                inequality(left, self.visit_expression(binary.right.as_ref())?)
-            }
             BinaryOperator::Disjunction => disjunction(left, binary.right.as_ref(), self),
             BinaryOperator::Conjunction => conjunction(left, binary.right.as_ref(), self),
+            _ => {
+                let right = self.visit_expression(binary.right.as_ref())?;
+                self.binary_values(binary, left, right)
+            }
         };
         Ok(Value::new(value_type?, binary.location))
     }
 
     fn visit_identifier(&mut self, identifier: &Identifier) -> Self::Return {
-        match self.env.get(&identifier.ident) {
-            Some(value) => Ok(Value::new(value.clone(), identifier.location)),
+        let value = match identifier.binding.get() {
+            Binding::Unresolved => self.env.get(&identifier.ident),
+            Binding::Local(depth) => self.env.get_at(depth, &identifier.ident),
+            Binding::Global => self.env.get_global(&identifier.ident),
+        };
+        match value {
+            Some(value) => Ok(Value::new(value, identifier.location)),
             None => Err(RuntimeError::UnboundName(
                 identifier.location,
                 identifier.ident.to_string(),
@@ -100,9 +62,13 @@ impl ExpressionVisitor for Evaluator {
     }
 
     fn visit_assignment(&mut self, assignment: &Assignment) -> Self::Return {
-        let expr = self.visit_expression(&assignment.expr)?;
-        self.env
-            .assign(assignment.ident.ident.clone(), expr.clone())?;
+        let expr = match assignment.expr.as_ref() {
+            Expression::BinaryOperation(binary) if is_append(assignment, binary) => {
+                self.append(assignment, binary)?
+            }
+            expr => self.visit_expression(expr)?,
+        };
+        self.assign(&assignment.ident, expr.clone())?;
         Ok(expr)
     }
 
@@ -114,74 +80,10 @@ impl ExpressionVisitor for Evaluator {
             arguments.push(self.visit_expression(argument)?.value)
         }
 
-        match callee.value {
-            ValueType::NativeFunction(f, arity) => {
-                if arguments.len() != arity {
-                    Err(RuntimeError::InvalidArgumentCount(
-                        call.location,
-                        arity,
-                        arguments.len(),
-                    ))
-                } else {
-                    Ok(Value {
-                        value: f(arguments, call.location)?,
-                        location: call.location,
-                    })
-                }
-            }
-            ValueType::Function(f) => {
-                if arguments.len() != f.args.len() {
-                    Err(RuntimeError::InvalidArgumentCount(
-                        call.location,
-                        f.args.len(),
-                        arguments.len(),
-                    ))
-                } else {
-                    self.env.push_env();
-                    for (arg, value) in f.args.iter().zip(arguments) {
-                        self.env.define(arg.ident.clone(), value);
-                    }
-                    let mut ret = ValueType::Nil;
-                    for stmt in &f.body.stmts {
-                        match self.visit_statement(stmt) {
-                            Ok(()) => (),
-                            Err(RuntimeError::Return(value)) => {
-                                ret = value.value;
-                                break;
-                            }
-                            Err(err) => return Err(err),
-                        }
-                    }
-                    self.env.pop_env();
-
-                    Ok(Value {
-                        location: f.span,
-                        value: ret,
-                    })
-                }
-            }
-            ValueType::Class(class) => {
-                if arguments.len() != 0 {
-                    Err(RuntimeError::InvalidArgumentCount(
-                        call.location,
-                        0,
-                        arguments.len(),
-                    ))
-                } else {
-                    Ok(Value {
-                        location: call.location,
-                        value: ValueType::Object(
-                            std::cell::RefCell::new(Object {
-                                properties: HashMap::new(),
-                                class,
-                            })
-                            .into(),
-                        ),
-                    })
-                }
-            }
-            _ => Err(RuntimeError::NotCallable(callee.location)),
-        }
+        Ok(Value::new(
+            self.call_value(&callee, arguments, call.location)?,
+            call.location,
+        ))
     }
 
     fn visit_get(&mut self, get: &Get) -> Self::Return {
@@ -200,14 +102,17 @@ impl ExpressionVisitor for Evaluator {
 
         let value = if let Some(value) = obj.properties.get(&get.name.ident) {
             value.clone()
-        } else if let Some(method) = obj.class.methods.get(&get.name.ident) {
+        } else if let Some(method) = obj.class.find_method(&get.name.ident) {
             Value {
-                value: ValueType::Function(method.clone()),
+                value: ValueType::BoundMethod(BoundMethod {
+                    method,
+                    this: obj_ref.clone(),
+                }),
                 location: get.name.location,
             }
         } else {
             return Err(RuntimeError::UndefinedProperty(
-                obj.clone(),
+                obj.class.clone(),
                 get.name.clone(),
             ));
         };
@@ -228,9 +133,190 @@ impl ExpressionVisitor for Evaluator {
             .insert(set.name.ident.clone(), value.clone());
         Ok(value)
     }
+
+    fn visit_this(&mut self, location: CodeSpan) -> Self::Return {
+        match self.env.get("this") {
+            Some(value) => Ok(Value::new(value, location)),
+            None => Err(RuntimeError::UnboundName(location, "this".to_string())),
+        }
+    }
+
+    fn visit_super(&mut self, expr: &Super) -> Self::Return {
+        let (superclass, this) = match (self.env.get("super"), self.env.get("this")) {
+            (Some(ValueType::Class(superclass)), Some(ValueType::Object(this))) => {
+                (superclass, this)
+            }
+            _ => {
+                return Err(RuntimeError::UnboundName(
+                    expr.location,
+                    "super".to_string(),
+                ))
+            }
+        };
+        match superclass.find_method(&expr.method.ident) {
+            Some(method) => Ok(Value::new(
+                ValueType::BoundMethod(BoundMethod { method, this }),
+                expr.location,
+            )),
+            None => Err(RuntimeError::UndefinedProperty(
+                superclass,
+                expr.method.clone(),
+            )),
+        }
+    }
+}
+
+pub(super) fn literal_value(literal: &Literal) -> ValueType {
+    match &literal.value {
+        LiteralValue::StringLiteral(s) => ValueType::String(s.clone()),
+        LiteralValue::NumberLiteral(n) => ValueType::Number(*n),
+        LiteralValue::True => ValueType::Boolean(true),
+        LiteralValue::False => ValueType::Boolean(false),
+        LiteralValue::Nil => ValueType::Nil,
+    }
+}
+
+pub(super) fn unary_operation(unary: &Unary, operand: ValueType) -> eval::Result<ValueType> {
+    match (unary.op, operand) {
+        (UnaryOperator::Minus, ValueType::Number(n)) => Ok(ValueType::Number(-n)),
+        (UnaryOperator::Plus, ValueType::Number(n)) => Ok(ValueType::Number(n)),
+        (UnaryOperator::Minus | UnaryOperator::Plus, v) => Err(MismatchedTypes(
+            unary.location,
+            Type::from(&v),
+            HashSet::from([Type::Number]),
+        )),
+        (UnaryOperator::Not, val) => Ok(ValueType::Boolean(!eval::is_truthy(&val))),
+    }
+}
+
+impl Evaluator {
+    fn assign(&mut self, ident: &Identifier, value: Value) -> eval::Result<()> {
+        let name = ident.ident.clone();
+        match ident.binding.get() {
+            Binding::Unresolved => self.env.assign(name, value),
+            Binding::Local(depth) => self.env.assign_at(depth, name, value),
+            Binding::Global => self.env.assign_global(name, value),
+        }
+    }
+
+    /// Applies an operator whose operands are both evaluated, accounting for
+    /// the strings it creates and warning about lost precision.
+    fn binary_values(
+        &mut self,
+        binary: &Binary,
+        left: Value,
+        right: Value,
+    ) -> eval::Result<ValueType> {
+        let integers = is_integer(&left) && is_integer(&right);
+        if let (BinaryOperator::Addition, ValueType::String(l), ValueType::String(r)) =
+            (binary.operator, &left.value, &right.value)
+        {
+            self.allocate(self.concatenation_size(l, r), binary.location)?;
+        }
+        let result = binary_operation(binary.operator, left, right, &self.options)?;
+        if self.options.precision_warnings && integers {
+            if let Some(warning) = precision_loss(binary.operator, &result, binary.location) {
+                self.warnings.push(warning);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Returns the number of bytes a concatenation allocates: only the
+    /// appended ones when the left string is extended in place.
+    fn concatenation_size(&self, left: &Rc<String>, right: &str) -> usize {
+        if self.options.copy_concatenations || Rc::strong_count(left) > 1 {
+            left.len() + right.len()
+        } else {
+            right.len()
+        }
+    }
+
+    /// Evaluates `name = name + expr`. When both sides are strings, the
+    /// variable is cleared before concatenating them, so that its string can
+    /// be extended in place instead of copied. It is left cleared if the
+    /// memory limit is exceeded.
+    fn append(&mut self, assignment: &Assignment, binary: &Binary) -> eval::Result<Value> {
+        let left = self.visit_expression(&binary.left)?;
+        let right = self.visit_expression(&binary.right)?;
+        if let (ValueType::String(_), ValueType::String(_)) = (&left.value, &right.value) {
+            let nil = Value::new(ValueType::Nil, left.location);
+            self.assign(&assignment.ident, nil)?;
+        }
+        Ok(Value::new(
+            self.binary_values(binary, left, right)?,
+            binary.location,
+        ))
+    }
 }
 
-fn addition(left: Value, right: Value) -> eval::Result<ValueType> {
+/// Returns whether an assignment appends to the variable it assigns, as in
+/// `name = name + expr`.
+fn is_append(assignment: &Assignment, binary: &Binary) -> bool {
+    binary.operator == BinaryOperator::Addition
+        && matches!(
+            binary.left.as_ref(),
+            Expression::Identifier(left)
+                if left.ident == assignment.ident.ident
+                    && left.binding.get() == assignment.ident.binding.get()
+        )
+}
+
+/// Largest integer such that it and every smaller integer can be represented
+/// exactly as a number.
+const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+
+/// Applies an operator whose operands are both always evaluated.
+pub(super) fn binary_operation(
+    operator: BinaryOperator,
+    left: Value,
+    right: Value,
+    options: &Options,
+) -> eval::Result<ValueType> {
+    let result = match operator {
+        BinaryOperator::Addition => addition(left, right, options),
+        BinaryOperator::Subtraction => subtraction(left, right),
+        BinaryOperator::Multiplication => multiplication(left, right),
+        BinaryOperator::Division => division(left, right),
+        BinaryOperator::Modulo => modulo(left, right),
+        BinaryOperator::StrictInferiority => strict_inferiority(left, right),
+        BinaryOperator::Inferiority => inferiority(left, right),
+        BinaryOperator::StrictSuperiority => strict_superiority(left, right),
+        BinaryOperator::Superiority => superiority(left, right),
+        BinaryOperator::Equality => equality(left, right, options),
+        BinaryOperator::Inequality => inequality(left, right, options),
+        BinaryOperator::Disjunction | BinaryOperator::Conjunction => {
+            unreachable!("short-circuiting operators are evaluated lazily")
+        }
+    };
+    result.map_err(|error| match error {
+        MismatchedTypes(span, actual, expected) => {
+            RuntimeError::InvalidOperand(span, operator, actual, expected)
+        }
+        error => error,
+    })
+}
+
+fn is_integer(value: &Value) -> bool {
+    matches!(value.value, ValueType::Number(n) if n.fract() == 0.0)
+}
+
+/// Checks whether an arithmetic operation on integers lost precision.
+fn precision_loss(
+    operator: BinaryOperator,
+    result: &ValueType,
+    location: CodeSpan,
+) -> Option<Warning> {
+    match (operator, result) {
+        (
+            BinaryOperator::Addition | BinaryOperator::Subtraction | BinaryOperator::Multiplication,
+            ValueType::Number(n),
+        ) if n.abs() > MAX_SAFE_INTEGER => Some(Warning::PrecisionLoss(location, operator, *n)),
+        _ => None,
+    }
+}
+
+fn addition(left: Value, right: Value, options: &Options) -> eval::Result<ValueType> {
     if let Ok(l) = as_number(&left) {
         if let Ok(r) = as_number(&right) {
             Ok(ValueType::Number(l + r))
@@ -241,11 +327,17 @@ fn addition(left: Value, right: Value) -> eval::Result<ValueType> {
                 HashSet::from([Type::Number]),
             ))
         }
-    } else if let Ok(l) = as_string(&left) {
+    } else if let ValueType::String(mut l) = left.value {
         if let Ok(r) = as_string(&right) {
-            let mut l = (*l).clone();
-            l.push_str(&r);
-            Ok(ValueType::String(Rc::new(l)))
+            // Strings are immutable, but one that nothing else refers to, such
+            // as the result of a previous concatenation, can be extended in
+            // place unless the options ask for a copy.
+            if options.copy_concatenations {
+                Ok(ValueType::String(Rc::new(format!("{}{}", l, r))))
+            } else {
+                Rc::make_mut(&mut l).push_str(&r);
+                Ok(ValueType::String(l))
+            }
         } else {
             Err(MismatchedTypes(
                 right.location,
@@ -271,13 +363,27 @@ fn multiplication(left: Value, right: Value) -> eval::Result<ValueType> {
 }
 
 fn division(left: Value, right: Value) -> eval::Result<ValueType> {
-    if right.value == ValueType::Number(0.0) {
+    let (dividend, divisor) = (as_number(&left)?, as_number(&right)?);
+    // Also true for -0.
+    if divisor == 0.0 {
         return Err(DivisionByZero(CodeSpan::combine(
             left.location,
             right.location,
         )));
     }
-    Ok(ValueType::Number(as_number(&left)? / as_number(&right)?))
+    Ok(ValueType::Number(dividend / divisor))
+}
+
+fn modulo(left: Value, right: Value) -> eval::Result<ValueType> {
+    let (dividend, divisor) = (as_number(&left)?, as_number(&right)?);
+    // Also true for -0.
+    if divisor == 0.0 {
+        return Err(DivisionByZero(CodeSpan::combine(
+            left.location,
+            right.location,
+        )));
+    }
+    Ok(ValueType::Number(dividend % divisor))
 }
 
 fn strict_inferiority(left: Value, right: Value) -> eval::Result<ValueType> {
@@ -296,24 +402,43 @@ fn superiority(left: Value, right: Value) -> eval::Result<ValueType> {
     Ok(ValueType::Boolean(as_number(&left)? >= as_number(&right)?))
 }
 
-fn test_equality(left: &Value, right: &Value) -> bool {
+fn test_equality(left: &Value, right: &Value, options: &Options) -> bool {
     match (&left.value, &right.value) {
         (ValueType::Boolean(l), ValueType::Boolean(r)) => l == r,
         (ValueType::Nil, ValueType::Nil) => true,
-        (ValueType::Number(l), ValueType::Number(r)) => l == r,
+        (ValueType::Number(l), ValueType::Number(r)) => match options.float_tolerance {
+            Some(tolerance) => l == r || (l - r).abs() <= tolerance,
+            None => l == r,
+        },
         (ValueType::String(l), ValueType::String(r)) => l == r,
-        (ValueType::Object(_), ValueType::Object(_)) => todo!(),
+        (ValueType::Object(l), ValueType::Object(r)) => Rc::ptr_eq(l, r),
         (_, _) => false,
     }
 }
 
-fn equality(left: Value, right: Value) -> eval::Result<ValueType> {
-    let val = test_equality(&left, &right);
+/// Errors if the values have different types and equality is strict.
+fn check_comparable(left: &Value, right: &Value, options: &Options) -> eval::Result<()> {
+    let left_type = left.value.as_type();
+    let right_type = right.value.as_type();
+    if options.strict_equality && left_type != right_type {
+        return Err(MismatchedTypes(
+            right.location,
+            right_type,
+            HashSet::from([left_type]),
+        ));
+    }
+    Ok(())
+}
+
+fn equality(left: Value, right: Value, options: &Options) -> eval::Result<ValueType> {
+    check_comparable(&left, &right, options)?;
+    let val = test_equality(&left, &right, options);
     Ok(ValueType::Boolean(val))
 }
 
-fn inequality(left: Value, right: Value) -> eval::Result<ValueType> {
-    let val = !test_equality(&left, &right);
+fn inequality(left: Value, right: Value, options: &Options) -> eval::Result<ValueType> {
+    check_comparable(&left, &right, options)?;
+    let val = !test_equality(&left, &right, options);
     Ok(ValueType::Boolean(val))
 }
 
@@ -322,7 +447,7 @@ fn disjunction(
     right: &Expression,
     visitor: &mut Evaluator,
 ) -> eval::Result<ValueType> {
-    if eval::is_truthy(&left.value) {
+    if visitor.truthiness(&left)? {
         Ok(left.value)
     } else {
         visitor.visit_expression(right).map(|val| val.value)
@@ -334,7 +459,7 @@ fn conjunction(
     right: &Expression,
     visitor: &mut Evaluator,
 ) -> eval::Result<ValueType> {
-    if !eval::is_truthy(&left.value) {
+    if !visitor.truthiness(&left)? {
         Ok(left.value)
     } else {
         visitor.visit_expression(right).map(|val| val.value)
@@ -346,7 +471,7 @@ fn as_number(value: &Value) -> eval::Result<f64> {
         ValueType::Number(n) => Ok(n),
         _ => Err(MismatchedTypes(
             value.location,
-            Type::Number,
+            value.value.as_type(),
             HashSet::from([Type::Number]),
         )),
     }
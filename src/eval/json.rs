@@ -0,0 +1,449 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    iter::Peekable,
+    rc::Rc,
+    str::Chars,
+};
+
+use crate::{
+    ast::{
+        expressions::Identifier,
+        types::{Class, Object, Type, Value, ValueType},
+    },
+    code_span::CodeSpan,
+    eval::{Result, RuntimeError},
+};
+
+/// How deeply JSON objects can nest, so that deep values and documents are
+/// rejected instead of overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+/// Serializes a value to JSON. Nil, booleans, finite numbers and strings map to
+/// their JSON counterparts, and objects to JSON objects of their properties,
+/// sorted by name. Functions and classes, non-finite numbers, objects
+/// containing themselves and objects nested too deeply cannot be serialized.
+pub fn value_to_json(value: &Value) -> Result<String> {
+    let mut json = String::new();
+    write_json(value, &mut json, &mut Vec::new())?;
+    Ok(json)
+}
+
+/// Writes a value as JSON. `parents` holds the objects being serialized
+/// around the value, to detect cycles.
+fn write_json(
+    value: &Value,
+    json: &mut String,
+    parents: &mut Vec<*const RefCell<Object>>,
+) -> Result<()> {
+    match &value.value {
+        ValueType::Nil => json.push_str("null"),
+        ValueType::Boolean(b) => json.push_str(if *b { "true" } else { "false" }),
+        ValueType::Number(n) if n.is_finite() => json.push_str(&n.to_string()),
+        ValueType::Number(n) => {
+            return Err(RuntimeError::InvalidArgument(
+                value.location,
+                format!("{} cannot be represented in JSON", n),
+            ))
+        }
+        ValueType::String(s) => write_json_string(s, json),
+        ValueType::Object(object) => {
+            if parents.contains(&Rc::as_ptr(object)) {
+                return Err(RuntimeError::InvalidArgument(
+                    value.location,
+                    "an object containing itself cannot be represented in JSON".to_string(),
+                ));
+            }
+            if parents.len() == MAX_DEPTH {
+                return Err(RuntimeError::InvalidArgument(
+                    value.location,
+                    format!(
+                        "objects nested deeper than {} cannot be represented in JSON",
+                        MAX_DEPTH
+                    ),
+                ));
+            }
+            parents.push(Rc::as_ptr(object));
+            let object = object.borrow();
+            let mut properties = object.properties.iter().collect::<Vec<_>>();
+            properties.sort_by_key(|(name, _)| *name);
+            json.push('{');
+            for (i, (name, property)) in properties.into_iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                write_json_string(name, json);
+                json.push(':');
+                write_json(property, json, parents)?;
+            }
+            json.push('}');
+            parents.pop();
+        }
+        v => {
+            return Err(RuntimeError::MismatchedTypes(
+                value.location,
+                v.as_type(),
+                HashSet::from([
+                    Type::Nil,
+                    Type::Boolean,
+                    Type::Number,
+                    Type::String,
+                    Type::Object,
+                ]),
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Writes a string as a JSON string literal.
+fn write_json_string(s: &str, json: &mut String) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            '\u{8}' => json.push_str("\\b"),
+            '\u{c}' => json.push_str("\\f"),
+            c if c < ' ' => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// Parses a JSON document into a value. JSON objects become instances of an
+/// `Object` class holding their members as properties. Arrays cannot be
+/// represented, as Lox has no lists. `span` locates the values and errors.
+pub fn json_to_value(json: &str, span: CodeSpan) -> Result<ValueType> {
+    let mut parser = JsonParser {
+        chars: json.chars().peekable(),
+        span,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        None => Ok(value),
+        Some(c) => Err(parser.error(format!("unexpected '{}' after JSON value", c))),
+    }
+}
+
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>,
+    span: CodeSpan,
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, message: String) -> RuntimeError {
+        RuntimeError::InvalidArgument(self.span, format!("invalid JSON: {}", message))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .next_if(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+            .is_some()
+        {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(format!("expected '{}', got '{}'", expected, c))),
+            None => Err(self.error(format!("expected '{}', got end of input", expected))),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: ValueType) -> Result<ValueType> {
+        for expected in keyword.chars() {
+            if self.chars.next() != Some(expected) {
+                return Err(self.error(format!("expected '{}'", keyword)));
+            }
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<ValueType> {
+        self.skip_whitespace();
+        match self.chars.peek().copied() {
+            Some('n') => self.keyword("null", ValueType::Nil),
+            Some('t') => self.keyword("true", ValueType::Boolean(true)),
+            Some('f') => self.keyword("false", ValueType::Boolean(false)),
+            Some('"') => Ok(ValueType::String(Rc::new(self.string()?))),
+            Some('-' | '0'..='9') => self.number(),
+            Some('{') => self.object(),
+            Some('[') => Err(self.error("arrays are not supported".to_string())),
+            Some(c) => Err(self.error(format!("unexpected '{}'", c))),
+            None => Err(self.error("unexpected end of input".to_string())),
+        }
+    }
+
+    fn number(&mut self) -> Result<ValueType> {
+        let mut number = String::new();
+        while let Some(c) = self
+            .chars
+            .next_if(|c| matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+        {
+            number.push(c);
+        }
+        number
+            .parse()
+            .map(ValueType::Number)
+            .map_err(|_| self.error(format!("invalid number '{}'", number)))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let c = match self.chars.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape sequence".to_string())),
+                    };
+                    string.push(c);
+                }
+                Some(c) if c < ' ' => {
+                    return Err(self.error("control character in string".to_string()))
+                }
+                Some(c) => string.push(c),
+                None => return Err(self.error("unterminated string".to_string())),
+            }
+        }
+    }
+
+    /// Reads the code unit of a `\u` escape, combining surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char> {
+        let high = self.hex_code_unit()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+                return Err(self.error("unpaired surrogate".to_string()));
+            }
+            let low = self.hex_code_unit()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("unpaired surrogate".to_string()));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate".to_string()))
+    }
+
+    fn hex_code_unit(&mut self) -> Result<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("invalid unicode escape".to_string()))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn object(&mut self) -> Result<ValueType> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(format!("objects nested deeper than {}", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let properties = self.members()?;
+        self.depth -= 1;
+        let class = Rc::new(Class {
+            name: Identifier {
+                ident: "Object".to_string(),
+                location: self.span,
+                binding: Default::default(),
+            },
+            superclass: None,
+            methods: HashMap::new(),
+        });
+        Ok(ValueType::Object(Rc::new(RefCell::new(Object {
+            properties,
+            class,
+        }))))
+    }
+
+    /// Parses the braces and members of an object.
+    fn members(&mut self) -> Result<HashMap<String, Value>> {
+        self.expect('{')?;
+        let mut properties = HashMap::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'}').is_none() {
+            loop {
+                self.skip_whitespace();
+                let name = self.string()?;
+                self.expect(':')?;
+                let value = self.value()?;
+                properties.insert(name, Value::new(value, self.span));
+                self.skip_whitespace();
+                match self.chars.next() {
+                    Some(',') => {}
+                    Some('}') => break,
+                    _ => return Err(self.error("expected ',' or '}'".to_string())),
+                }
+            }
+        }
+        Ok(properties)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::{expressions::ExpressionVisitor, statements::StatementVisitor},
+        eval::{prelude, Evaluator},
+        parsing::{parse, parse_expression},
+        scanning::TokenStream,
+    };
+
+    /// Runs the code, then serializes the value of the expression.
+    fn json(code: &str, expr: &str) -> Result<String> {
+        let mut evaluator = Evaluator::capturing().0;
+        evaluator.register_prelude(prelude());
+        for stmt in parse(&mut TokenStream::new(code)).unwrap().stmts {
+            evaluator.visit_statement(&stmt).unwrap();
+        }
+        let expr = parse_expression(&mut TokenStream::new(expr)).unwrap();
+        value_to_json(&evaluator.visit_expression(&expr).unwrap())
+    }
+
+    #[test]
+    fn scalars() {
+        assert_eq!(json("", "nil").unwrap(), "null");
+        assert_eq!(json("", "true").unwrap(), "true");
+        assert_eq!(json("", "false").unwrap(), "false");
+        assert_eq!(json("", "42").unwrap(), "42");
+        assert_eq!(json("", "-1.5").unwrap(), "-1.5");
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(json("", r#""hello""#).unwrap(), r#""hello""#);
+        assert_eq!(
+            json("", r#""a \"quote\"\\ and\n\ttabs\0""#).unwrap(),
+            r#""a \"quote\"\\ and\n\ttabs\u0000""#
+        );
+    }
+
+    #[test]
+    fn objects() {
+        let code = "class Point {} var p = Point(); p.y = 2; p.x = \"one\"; \
+            p.inner = Point(); p.inner.ok = true;";
+        assert_eq!(
+            json(code, "p").unwrap(),
+            r#"{"inner":{"ok":true},"x":"one","y":2}"#
+        );
+        assert_eq!(json("class A {}", "A()").unwrap(), "{}");
+    }
+
+    #[test]
+    fn unserializable_values() {
+        assert!(matches!(
+            json("fun f() {}", "f"),
+            Err(RuntimeError::MismatchedTypes(_, Type::Function, _))
+        ));
+        assert!(matches!(
+            json("class A {}", "A"),
+            Err(RuntimeError::MismatchedTypes(_, Type::Class, _))
+        ));
+        assert!(matches!(
+            json("", "clock"),
+            Err(RuntimeError::MismatchedTypes(_, Type::NativeFunction, _))
+        ));
+        assert!(matches!(
+            json("class A {} var a = A(); a.b = A(); a.b.a = a;", "a"),
+            Err(RuntimeError::InvalidArgument(..))
+        ));
+    }
+
+    #[test]
+    fn deep_objects() {
+        let code = |depth| {
+            format!(
+                "class A {{}} var a = A(); var b = a; \
+                for (var i = 1; i < {}; i = i + 1) {{ b.b = A(); b = b.b; }}",
+                depth
+            )
+        };
+        assert!(json(&code(MAX_DEPTH), "a").is_ok());
+        assert!(matches!(
+            json(&code(MAX_DEPTH + 1), "a"),
+            Err(RuntimeError::InvalidArgument(..))
+        ));
+        assert!(matches!(
+            json(&code(1000), "a"),
+            Err(RuntimeError::InvalidArgument(..))
+        ));
+    }
+
+    fn round_trip(json: &str) -> Result<String> {
+        let span = CodeSpan::synthetic();
+        value_to_json(&Value::new(json_to_value(json, span)?, span))
+    }
+
+    #[test]
+    fn parse_json() {
+        assert_eq!(round_trip(" null ").unwrap(), "null");
+        assert!(round_trip("[]").is_err());
+        assert_eq!(round_trip("-1.5e2").unwrap(), "-150");
+        assert_eq!(
+            round_trip(r#""a\"b\u00e9\ud83d\ude00\n""#).unwrap(),
+            "\"a\\\"b\u{e9}\u{1f600}\\n\""
+        );
+        assert!(round_trip(r#"{ "b": {"c": [] } }"#).is_err());
+        assert_eq!(
+            round_trip(r#"{ "b": {"c": false}, "a": true }"#).unwrap(),
+            r#"{"a":true,"b":{"c":false}}"#
+        );
+    }
+
+    #[test]
+    fn parse_deep_json() {
+        let nested = |depth| "{\"a\":".repeat(depth) + "1" + &"}".repeat(depth);
+        assert!(round_trip(&nested(MAX_DEPTH)).is_ok());
+        assert!(matches!(
+            round_trip(&nested(5000)),
+            Err(RuntimeError::InvalidArgument(..))
+        ));
+    }
+
+    #[test]
+    fn parse_malformed_json() {
+        for json in [
+            "",
+            "{",
+            r#"{"a" 1}"#,
+            r#"{"a": 1,}"#,
+            "tru",
+            "1 2",
+            r#""abc"#,
+            "-",
+        ] {
+            assert!(
+                matches!(round_trip(json), Err(RuntimeError::InvalidArgument(..))),
+                "{}",
+                json
+            );
+        }
+    }
+}
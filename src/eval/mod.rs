@@ -1,17 +1,35 @@
+use std::{cell::RefCell, rc::Rc};
+
 pub use builtins::prelude;
-use runtime_error::RuntimeError;
+pub use callable::Callable;
+pub use constants::{eval_constant, fold_constants};
+pub use json::{json_to_value, value_to_json};
+pub use options::Options;
+pub use resolver::Resolver;
+pub use runtime_error::RuntimeError;
+pub use warning::Warning;
 
 use crate::{
-    ast::types::{NativeFunction, Type, ValueType},
-    eval::{environment::Environment, output_stream::OutputStream},
+    ast::types::{BoundMethod, Native, NativeFunction, Type, Value, ValueType, VARIADIC},
+    code_span::CodeSpan,
+    eval::{
+        environment::{Environment, ScopeGuard},
+        output_stream::OutputStream,
+    },
 };
 
 mod builtins;
+mod callable;
+mod constants;
 mod environment;
 mod expressions;
+mod json;
+mod options;
 pub mod output_stream;
+mod resolver;
 mod runtime_error;
 mod statements;
+mod warning;
 
 #[cfg(test)]
 mod tests;
@@ -19,6 +37,10 @@ mod tests;
 pub struct Evaluator {
     env: Environment,
     out: OutputStream,
+    options: Options,
+    warnings: Vec<Warning>,
+    allocated: usize,
+    call_depth: usize,
 }
 
 impl Evaluator {
@@ -26,19 +48,156 @@ impl Evaluator {
         Evaluator {
             env: Environment::new(),
             out,
+            options: Options::default(),
+            warnings: Vec::new(),
+            allocated: 0,
+            call_depth: 0,
+        }
+    }
+
+    pub fn options_mut(&mut self) -> &mut Options {
+        &mut self.options
+    }
+
+    /// Returns the warnings raised since the last call.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Creates an evaluator whose output is captured in the returned buffer.
+    pub fn capturing() -> (Self, Rc<RefCell<String>>) {
+        let buffer = Rc::new(RefCell::new(String::new()));
+        (Evaluator::new(OutputStream::Buffer(buffer.clone())), buffer)
+    }
+
+    /// Redirects the output of the evaluator and returns the previous stream.
+    pub fn set_output(&mut self, out: OutputStream) -> OutputStream {
+        std::mem::replace(&mut self.out, out)
+    }
+
+    /// Accounts for `bytes` newly allocated bytes, failing if this exceeds the
+    /// memory limit.
+    fn allocate(&mut self, bytes: usize, location: CodeSpan) -> Result<()> {
+        self.allocated += bytes;
+        match self.options.memory_limit {
+            Some(limit) if self.allocated > limit => {
+                Err(RuntimeError::MemoryLimitExceeded(location, limit))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns whether a value is truthy. Objects whose class defines a
+    /// `__bool__` method are truthy if that method returns a truthy value;
+    /// other objects are always truthy.
+    fn truthiness(&mut self, value: &Value) -> Result<bool> {
+        let method = match &value.value {
+            ValueType::Object(object) => {
+                let method = object.borrow().class.find_method(BOOL_METHOD);
+                method.map(|method| BoundMethod {
+                    method,
+                    this: object.clone(),
+                })
+            }
+            _ => None,
+        };
+        match method {
+            Some(method) if method.arity() != 0 => Err(RuntimeError::InvalidArgumentCount(
+                value.location,
+                0,
+                method.arity(),
+            )),
+            Some(method) => Ok(is_truthy(&method.call(self, Vec::new(), value.location)?)),
+            None => Ok(is_truthy(&value.value)),
+        }
+    }
+
+    /// Returns the value of a variable visible from the current scope.
+    pub fn get(&self, name: &str) -> Option<ValueType> {
+        self.env.get(name)
+    }
+
+    /// Returns the global variables and their values, sorted by name.
+    pub fn globals(&self) -> Vec<(String, ValueType)> {
+        self.env.dump()
+    }
+
+    /// Calls a function, method, class or native function, so that the host
+    /// can invoke callbacks defined in Lox.
+    pub fn call(&mut self, callee: ValueType, arguments: Vec<ValueType>) -> Result<ValueType> {
+        let location = CodeSpan::synthetic();
+        self.call_value(&Value::new(callee, location), arguments, location)
+    }
+
+    /// Calls a value at `location`, checking that it is callable with this
+    /// number of arguments and that the maximum call depth is not exceeded.
+    fn call_value(
+        &mut self,
+        callee: &Value,
+        arguments: Vec<ValueType>,
+        location: CodeSpan,
+    ) -> Result<ValueType> {
+        let callable = callable::as_callable(&callee.value)
+            .ok_or(RuntimeError::NotCallable(callee.location))?;
+        if callable.arity() != VARIADIC && arguments.len() != callable.arity() {
+            return Err(RuntimeError::InvalidArgumentCount(
+                location,
+                callable.arity(),
+                arguments.len(),
+            ));
         }
+        self.call_depth += 1;
+        let result = match self.options.max_call_depth {
+            Some(max) if self.call_depth > max => {
+                Err(RuntimeError::CallDepthExceeded(location, max))
+            }
+            _ => callable.call(self, arguments, location),
+        };
+        self.call_depth -= 1;
+        result
+    }
+
+    /// Pushes a new scope that is popped when the returned guard is dropped.
+    fn scope(&mut self) -> ScopeGuard<'_, Self> {
+        ScopeGuard::new(self)
     }
 
     pub fn register_prelude(&mut self, prelude: Vec<(&str, NativeFunction, usize)>) {
         for (name, function, arity) in prelude {
-            self.env
-                .define(name.to_string(), ValueType::NativeFunction(function, arity));
+            self.env.define(
+                name.to_string(),
+                ValueType::NativeFunction(Native { function, arity }),
+            );
         }
     }
+
+    /// Registers only the functions of the prelude whose name is in `names`,
+    /// so that embedders can choose which builtins scripts can use.
+    pub fn register_prelude_subset(
+        &mut self,
+        prelude: Vec<(&str, NativeFunction, usize)>,
+        names: &[&str],
+    ) {
+        self.register_prelude(
+            prelude
+                .into_iter()
+                .filter(|(name, _, _)| names.contains(name))
+                .collect(),
+        );
+    }
 }
 
 pub type Result<T> = std::result::Result<T, RuntimeError>;
 
+/// The name of the method that makes an object falsy.
+const BOOL_METHOD: &str = "__bool__";
+
 fn is_truthy(value: &ValueType) -> bool {
     !matches!(value, ValueType::Boolean(false) | ValueType::Nil)
 }
+
+impl AsMut<Environment> for Evaluator {
+    fn as_mut(&mut self) -> &mut Environment {
+        &mut self.env
+    }
+}
@@ -0,0 +1,26 @@
+/// Configures the behaviour of an `Evaluator`.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Warn when an arithmetic operation on integers produces a result that
+    /// cannot be represented exactly.
+    pub precision_warnings: bool,
+    /// When set, numbers closer to each other than this tolerance are
+    /// considered equal. Equality is exact by default.
+    pub float_tolerance: Option<f64>,
+    /// When set, the total number of bytes allocated for strings and objects
+    /// during evaluation may not exceed this limit.
+    pub memory_limit: Option<usize>,
+    /// When set, a loop running more iterations than this fails instead of
+    /// running forever.
+    pub max_iterations: Option<usize>,
+    /// When set, calls nested deeper than this fail instead of overflowing
+    /// the stack.
+    pub max_call_depth: Option<usize>,
+    /// Comparing values of different types with `==` or `!=` is an error
+    /// instead of being false.
+    pub strict_equality: bool,
+    /// Concatenating strings always copies the left one, instead of extending
+    /// it in place when nothing else refers to it. The result is the same,
+    /// only slower.
+    pub copy_concatenations: bool,
+}
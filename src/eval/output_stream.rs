@@ -1,21 +1,48 @@
 use std::{
+    cell::RefCell,
     fmt::Write,
     io::{Stdout, Write as WriteIo},
+    rc::Rc,
 };
 
 pub enum OutputStream {
-    File(String),
+    /// Accumulates the output in a string that can be shared with the host.
+    Buffer(Rc<RefCell<String>>),
     StdOut(Stdout),
+    /// Writes the output to any byte sink, such as a file or a socket.
+    Writer(Box<dyn WriteIo>),
+}
+
+impl OutputStream {
+    /// Flushes the output so that it is visible immediately. Buffers are
+    /// always up to date, so this does nothing for them.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputStream::Buffer(_) => Ok(()),
+            OutputStream::StdOut(out) => out.flush(),
+            OutputStream::Writer(writer) => writer.flush(),
+        }
+    }
+
+    /// Flushes standard output after a print, so that the output of the
+    /// prompt shows up immediately. Other sinks, such as files, keep
+    /// buffering until they are flushed.
+    pub(super) fn flush_print(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputStream::StdOut(out) => out.flush(),
+            OutputStream::Buffer(_) | OutputStream::Writer(_) => Ok(()),
+        }
+    }
 }
 
 impl Write for OutputStream {
     fn write_str(&mut self, s: &str) -> std::fmt::Result {
         match self {
-            OutputStream::File(str) => str.write_str(s),
-            OutputStream::StdOut(out) => out
-                .write(s.as_bytes())
-                .map(|_| ())
-                .map_err(|_| std::fmt::Error),
+            OutputStream::Buffer(buffer) => buffer.borrow_mut().write_str(s),
+            OutputStream::StdOut(out) => out.write_all(s.as_bytes()).map_err(|_| std::fmt::Error),
+            OutputStream::Writer(writer) => {
+                writer.write_all(s.as_bytes()).map_err(|_| std::fmt::Error)
+            }
         }
     }
 }
@@ -0,0 +1,524 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        declarations::{ClassDeclaration, FunctionDeclaration, VariableDeclaration},
+        expressions::{
+            Assignment, Binary, Binding, Call, Expression, ExpressionVisitor, Get, Identifier,
+            Literal, Set, Super, Unary,
+        },
+        statements::{
+            Assert, Conditional, ForIn, Print, Statement, StatementVisitor, Statements, WhileLoop,
+        },
+        types::Function,
+    },
+    code_span::CodeSpan,
+    parsing::ParsingError,
+};
+
+/// Statically checks a program before it is evaluated, collecting every error
+/// found instead of stopping at the first one. Resolving also sets the
+/// binding of every variable used, so that the evaluator finds it in the
+/// scope it was declared in instead of searching every scope.
+#[derive(Default)]
+pub struct Resolver {
+    /// Names declared in the enclosing local scopes, innermost last, along
+    /// with whether their initializer has been resolved.
+    scopes: Vec<HashMap<String, bool>>,
+    /// Names declared at the top level of the program or by the host.
+    globals: HashSet<String>,
+    check_unbound_names: bool,
+    /// Whether the code being resolved is inside a class, where `this` is
+    /// bound.
+    in_class: bool,
+    /// Whether the code being resolved is inside a class with a superclass,
+    /// where `super` is bound.
+    in_subclass: bool,
+    /// Whether the code being resolved is inside a function or a method,
+    /// where `return` is allowed.
+    in_function: bool,
+    /// The number of loops enclosing the code being resolved, within the
+    /// current function.
+    loop_depth: usize,
+    errors: Vec<ParsingError>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports references to names that are not declared in an enclosing
+    /// scope as `ParsingError::UndeclaredName`. Globals may be used before
+    /// their declaration, so that functions can call functions declared
+    /// later.
+    pub fn check_unbound_names(mut self, check: bool) -> Self {
+        self.check_unbound_names = check;
+        self
+    }
+
+    /// Declares a global defined outside the program, such as a native
+    /// function of the prelude.
+    pub fn declare_global(&mut self, name: &str) {
+        self.globals.insert(name.to_string());
+    }
+
+    /// Resolves a whole program, returning every error found.
+    pub fn resolve(mut self, program: &Statements) -> Result<(), Vec<ParsingError>> {
+        for stmt in &program.stmts {
+            if let Some(name) = declared_name(stmt) {
+                self.globals.insert(name.ident.clone());
+            }
+        }
+        for stmt in &program.stmts {
+            self.visit_statement(stmt);
+        }
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn declare(&mut self, name: &Identifier) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.ident.clone(), true);
+        }
+    }
+
+    /// Sets the binding of a variable to the innermost scope declaring it, or
+    /// to the globals if no local scope does.
+    fn bind(&mut self, name: &Identifier) {
+        let depth = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.ident));
+        match depth {
+            Some(depth) => name.binding.set(Binding::Local(depth)),
+            None => {
+                name.binding.set(Binding::Global);
+                if self.check_unbound_names && !self.globals.contains(&name.ident) {
+                    self.errors.push(ParsingError::UndeclaredName(
+                        name.location,
+                        name.ident.clone(),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, function: &Function) {
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let in_function = std::mem::replace(&mut self.in_function, true);
+        self.scopes.push(HashMap::new());
+        for arg in &function.args {
+            self.declare(arg);
+        }
+        self.resolve_statements(&function.body);
+        self.scopes.pop();
+        self.in_function = in_function;
+        self.loop_depth = loop_depth;
+    }
+
+    /// Resolves the statements of a block or a function body in the innermost
+    /// scope. The functions they declare are declared first, so that they can
+    /// call each other regardless of their order, as globals can.
+    fn resolve_statements(&mut self, stmts: &Statements) {
+        for stmt in &stmts.stmts {
+            if let Statement::FunctionDeclaration(decl) = stmt {
+                self.declare(&decl.name);
+            }
+        }
+        for stmt in &stmts.stmts {
+            self.visit_statement(stmt);
+        }
+    }
+
+    /// Resolves the body of a loop, where `break` and `continue` are allowed.
+    fn resolve_loop_body(&mut self, body: &Statement) {
+        self.loop_depth += 1;
+        self.visit_statement(body);
+        self.loop_depth -= 1;
+    }
+}
+
+/// Returns the name introduced by a declaration.
+fn declared_name(stmt: &Statement) -> Option<&Identifier> {
+    match stmt {
+        Statement::VariableDeclaration(decl) => Some(&decl.name),
+        Statement::FunctionDeclaration(decl) => Some(&decl.name),
+        Statement::ClassDeclaration(decl) => Some(&decl.name),
+        _ => None,
+    }
+}
+
+impl StatementVisitor for Resolver {
+    type Return = ();
+
+    fn visit_statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Print(print) => self.visit_print(print),
+            Statement::Assert(assert) => self.visit_assert(assert),
+            Statement::Expression(expr) => self.visit_expression(expr),
+            Statement::VariableDeclaration(decl) => self.visit_variable_declaration(decl),
+            Statement::ClassDeclaration(decl) => self.visit_class_declaration(decl),
+            Statement::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                self.resolve_statements(stmts);
+                self.scopes.pop();
+            }
+            Statement::Conditional(c) => self.visit_conditional(c),
+            Statement::WhileLoop(w) => self.visit_while_loop(w),
+            Statement::ForIn(l) => self.visit_for_in(l),
+            Statement::FunctionDeclaration(f) => self.visit_function_declaration(f),
+            Statement::Return(expr) => self.visit_return(expr),
+            Statement::Break(span) => {
+                if self.loop_depth == 0 {
+                    self.errors.push(ParsingError::BreakOutsideLoop(*span));
+                }
+            }
+            Statement::Continue(span) => {
+                if self.loop_depth == 0 {
+                    self.errors.push(ParsingError::ContinueOutsideLoop(*span));
+                }
+            }
+        }
+    }
+
+    fn visit_print(&mut self, print: &Print) {
+        self.visit_expression(&print.expr);
+    }
+
+    fn visit_assert(&mut self, assert: &Assert) {
+        self.visit_expression(&assert.condition);
+    }
+
+    fn visit_variable_declaration(&mut self, decl: &VariableDeclaration) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(decl.name.ident.clone(), false);
+        }
+        if let Some(initializer) = &decl.initializer {
+            self.visit_expression(initializer);
+        }
+        self.declare(&decl.name);
+    }
+
+    fn visit_class_declaration(&mut self, decl: &ClassDeclaration) {
+        self.declare(&decl.name);
+        if let Some(superclass) = &decl.superclass {
+            self.visit_identifier(superclass);
+            self.scopes
+                .push(HashMap::from([("super".to_string(), true)]));
+        }
+        let in_class = std::mem::replace(&mut self.in_class, true);
+        let in_subclass = std::mem::replace(&mut self.in_subclass, decl.superclass.is_some());
+        for method in &decl.methods {
+            self.resolve_function(&method.function);
+        }
+        self.in_class = in_class;
+        self.in_subclass = in_subclass;
+        if decl.superclass.is_some() {
+            self.scopes.pop();
+        }
+    }
+
+    fn visit_conditional(&mut self, cond: &Conditional) {
+        self.visit_expression(&cond.condition);
+        self.visit_statement(&cond.then_statement);
+        if let Some(else_statement) = &cond.else_statement {
+            self.visit_statement(else_statement);
+        }
+    }
+
+    fn visit_while_loop(&mut self, while_loop: &WhileLoop) {
+        self.visit_expression(&while_loop.condition);
+        self.resolve_loop_body(&while_loop.statement);
+        if let Some(increment) = &while_loop.increment {
+            self.visit_expression(increment);
+        }
+        if let Some(else_statement) = &while_loop.else_statement {
+            self.visit_statement(else_statement);
+        }
+    }
+
+    fn visit_for_in(&mut self, for_in: &ForIn) {
+        self.visit_expression(&for_in.iterable);
+        self.scopes
+            .push(HashMap::from([(for_in.variable.ident.clone(), true)]));
+        self.resolve_loop_body(&for_in.statement);
+        self.scopes.pop();
+        if let Some(else_statement) = &for_in.else_statement {
+            self.visit_statement(else_statement);
+        }
+    }
+
+    fn visit_function_declaration(&mut self, fd: &FunctionDeclaration) {
+        self.declare(&fd.name);
+        self.resolve_function(&fd.function);
+    }
+
+    fn visit_return(&mut self, expr: &Expression) {
+        if !self.in_function {
+            self.errors
+                .push(ParsingError::ReturnOutsideFunction(expr.get_location()));
+        }
+        self.visit_expression(expr);
+    }
+}
+
+impl ExpressionVisitor for Resolver {
+    type Return = ();
+
+    fn visit_literal(&mut self, _: &Literal) {}
+
+    fn visit_unary(&mut self, unary: &Unary) {
+        self.visit_expression(&unary.expr);
+    }
+
+    fn visit_binary(&mut self, binary: &Binary) {
+        self.visit_expression(&binary.left);
+        self.visit_expression(&binary.right);
+    }
+
+    fn visit_identifier(&mut self, identifier: &Identifier) {
+        let scope = self.scopes.last();
+        if scope.and_then(|scope| scope.get(&identifier.ident)) == Some(&false) {
+            self.errors.push(ParsingError::SelfReferentialInitializer(
+                identifier.location,
+                identifier.ident.clone(),
+            ));
+        }
+        self.bind(identifier);
+    }
+
+    fn visit_assignment(&mut self, assignment: &Assignment) {
+        self.visit_expression(&assignment.expr);
+        self.bind(&assignment.ident);
+    }
+
+    fn visit_call(&mut self, call: &Call) {
+        self.visit_expression(&call.callee);
+        for argument in &call.arguments {
+            self.visit_expression(argument);
+        }
+    }
+
+    fn visit_get(&mut self, get: &Get) {
+        self.visit_expression(&get.object);
+    }
+
+    fn visit_set(&mut self, set: &Set) {
+        self.visit_expression(&set.object);
+        self.visit_expression(&set.value);
+    }
+
+    fn visit_this(&mut self, location: CodeSpan) {
+        if !self.in_class {
+            self.errors.push(ParsingError::ThisOutsideClass(location));
+        }
+    }
+
+    fn visit_super(&mut self, expr: &Super) {
+        if !self.in_subclass {
+            self.errors
+                .push(ParsingError::SuperOutsideSubclass(expr.location));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parsing::parse, scanning::TokenStream};
+
+    fn resolve(code: &str) -> Result<(), Vec<ParsingError>> {
+        let program = parse(&mut TokenStream::new(code)).unwrap();
+        let mut resolver = Resolver::new().check_unbound_names(true);
+        resolver.declare_global("clock");
+        resolver.resolve(&program)
+    }
+
+    fn undeclared_names(code: &str) -> Vec<String> {
+        resolve(code)
+            .unwrap_err()
+            .into_iter()
+            .map(|error| match error {
+                ParsingError::UndeclaredName(_, name) => name,
+                e => panic!("unexpected error {}", e),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn typo() {
+        assert_eq!(undeclared_names("var count = 1; print cuont;"), ["cuont"]);
+        assert_eq!(undeclared_names("fun f(a) { return b; }"), ["b"]);
+        assert_eq!(
+            undeclared_names("undeclared = 1; print x + y;"),
+            ["undeclared", "x", "y"]
+        );
+    }
+
+    #[test]
+    fn forward_reference() {
+        assert!(resolve("fun f() { return g(); } fun g() { return 1; } print f();").is_ok());
+        assert!(resolve("print clock();").is_ok());
+        assert!(resolve("{ fun f() { return g(); } fun g() { return 1; } print f(); }").is_ok());
+    }
+
+    #[test]
+    fn scopes() {
+        assert!(resolve("fun f(a) { var b = a; { var c = b; print a + b + c; } }").is_ok());
+        assert!(resolve("fun fact(n) { if (n < 2) return 1; return n * fact(n - 1); }").is_ok());
+        assert_eq!(undeclared_names("{ var a = 1; } { print a; }"), ["a"]);
+        assert_eq!(undeclared_names("{ print a; var a = 1; }"), ["a"]);
+    }
+
+    #[test]
+    fn this_outside_class() {
+        for code in ["print this;", "fun f() { return this; }"] {
+            let errors = Resolver::new()
+                .resolve(&parse(&mut TokenStream::new(code)).unwrap())
+                .unwrap_err();
+            assert!(
+                matches!(errors[..], [ParsingError::ThisOutsideClass(_)]),
+                "{}",
+                code
+            );
+        }
+        assert!(resolve("class A { get() { return this.a; } }").is_ok());
+        assert!(resolve("class A { m() { fun f() { return this; } return f(); } }").is_ok());
+    }
+
+    #[test]
+    fn super_outside_subclass() {
+        for code in [
+            "print super.m;",
+            "class A { m() { return super.m(); } }",
+            "class A {} class B < A { m() { class C { n() { return super.n; } } } }",
+        ] {
+            let errors = Resolver::new()
+                .resolve(&parse(&mut TokenStream::new(code)).unwrap())
+                .unwrap_err();
+            assert!(
+                matches!(errors[..], [ParsingError::SuperOutsideSubclass(_)]),
+                "{}",
+                code
+            );
+        }
+        assert!(resolve("class A {} class B < A { m() { return super.m; } }").is_ok());
+    }
+
+    #[test]
+    fn break_outside_loop() {
+        for code in [
+            "break;",
+            "if (true) break;",
+            "while (true) { fun f() { break; } }",
+            "while (false) {} else break;",
+        ] {
+            let errors = Resolver::new()
+                .resolve(&parse(&mut TokenStream::new(code)).unwrap())
+                .unwrap_err();
+            assert!(
+                matches!(errors[..], [ParsingError::BreakOutsideLoop(_)]),
+                "{}",
+                code
+            );
+        }
+        assert!(resolve("while (true) break;").is_ok());
+        assert!(resolve("for (;;) { if (true) { break; } }").is_ok());
+        assert!(resolve("var o; for (var k in o) break;").is_ok());
+    }
+
+    #[test]
+    fn continue_outside_loop() {
+        for code in ["continue;", "while (true) { fun f() { continue; } }"] {
+            let errors = Resolver::new()
+                .resolve(&parse(&mut TokenStream::new(code)).unwrap())
+                .unwrap_err();
+            assert!(
+                matches!(errors[..], [ParsingError::ContinueOutsideLoop(_)]),
+                "{}",
+                code
+            );
+        }
+        assert!(resolve("for (;;) { if (true) continue; }").is_ok());
+    }
+
+    #[test]
+    fn return_outside_function() {
+        for code in ["return;", "return 1;", "{ if (true) return; }"] {
+            let errors = Resolver::new()
+                .resolve(&parse(&mut TokenStream::new(code)).unwrap())
+                .unwrap_err();
+            assert!(
+                matches!(errors[..], [ParsingError::ReturnOutsideFunction(_)]),
+                "{}",
+                code
+            );
+        }
+        assert!(resolve("fun f() { return; }").is_ok());
+        assert!(resolve("class A { m() { while (true) return 1; } }").is_ok());
+    }
+
+    /// Returns the bindings of the variables printed by the innermost block,
+    /// with each block being the last statement of the enclosing one.
+    fn printed_bindings(code: &str) -> Vec<Binding> {
+        let program = parse(&mut TokenStream::new(code)).unwrap();
+        Resolver::new().resolve(&program).unwrap();
+        let mut stmts = &program.stmts;
+        while let Some(Statement::Block(block)) = stmts.last() {
+            stmts = &block.stmts;
+        }
+        stmts
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Statement::Print(Print {
+                    expr: Expression::Identifier(identifier),
+                    ..
+                }) => Some(identifier.binding.get()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bindings() {
+        assert_eq!(
+            printed_bindings(
+                "var g; { var a; { var b; { var c; print c; print b; print a; print g; } } }"
+            ),
+            [
+                Binding::Local(0),
+                Binding::Local(1),
+                Binding::Local(2),
+                Binding::Global
+            ]
+        );
+        assert_eq!(
+            printed_bindings("{ var a; { var a; print a; } }"),
+            [Binding::Local(0)]
+        );
+    }
+
+    #[test]
+    fn self_referential_initializer() {
+        let errors = Resolver::new()
+            .resolve(&parse(&mut TokenStream::new("{ var a = 1; { var a = a + 1; } }")).unwrap())
+            .unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [ParsingError::SelfReferentialInitializer(_, name)] if name == "a"
+        ));
+        assert!(resolve("var a = 1; var a = a + 1;").is_ok());
+        assert!(resolve("{ var a = 1; { var b = a; } }").is_ok());
+    }
+
+    #[test]
+    fn unchecked_by_default() {
+        let program = parse(&mut TokenStream::new("print a;")).unwrap();
+        assert!(Resolver::new().resolve(&program).is_ok());
+    }
+}
@@ -2,12 +2,13 @@ use std::{
     collections::HashSet,
     error::Error,
     fmt::{Display, Formatter},
+    rc::Rc,
 };
 
 use crate::{
     ast::{
-        expressions::Identifier,
-        types::{Object, Value},
+        expressions::{BinaryOperator, Identifier},
+        types::{Class, Value},
     },
     code_span::CodeSpan,
     eval::Type,
@@ -19,6 +20,12 @@ pub enum RuntimeError {
     /// Type: the actual type of the value.
     /// HashSet<Type>: the allowed types for the value.
     /// InvalidArgumentCount(span, expected count, actual count)
+    /// MemoryLimitExceeded(span, limit in bytes)
+    /// IterationCapExceeded(span of the loop condition, maximum iterations)
+    /// CallDepthExceeded(span of the call, maximum depth)
+    /// AssertionFailed(span of the condition, source text of the condition)
+    /// InvalidArgument(span of the call, description of the problem)
+    /// InvalidOperand(span of the operand, operator, actual type, allowed types)
     MismatchedTypes(CodeSpan, Type, HashSet<Type>),
     DivisionByZero(CodeSpan),
     UnboundName(CodeSpan, String),
@@ -26,8 +33,17 @@ pub enum RuntimeError {
     NotCallable(CodeSpan),
     InvalidArgumentCount(CodeSpan, usize, usize),
     Return(Value),
+    BreakSignal(CodeSpan),
+    ContinueSignal(CodeSpan),
     GetOnNonObject(Value),
-    UndefinedProperty(Object, Identifier),
+    UndefinedProperty(Rc<Class>, Identifier),
+    UnhashableValue(Value),
+    MemoryLimitExceeded(CodeSpan, usize),
+    IterationCapExceeded(CodeSpan, usize),
+    CallDepthExceeded(CodeSpan, usize),
+    AssertionFailed(CodeSpan, String),
+    InvalidArgument(CodeSpan, String),
+    InvalidOperand(CodeSpan, BinaryOperator, Type, HashSet<Type>),
 }
 
 impl RuntimeError {
@@ -40,8 +56,17 @@ impl RuntimeError {
             RuntimeError::NotCallable(span) => span,
             RuntimeError::InvalidArgumentCount(span, _, _) => span,
             RuntimeError::Return(value) => &value.location,
+            RuntimeError::BreakSignal(span) => span,
+            RuntimeError::ContinueSignal(span) => span,
             RuntimeError::GetOnNonObject(val) => &val.location,
             RuntimeError::UndefinedProperty(_, ident) => &ident.location,
+            RuntimeError::UnhashableValue(val) => &val.location,
+            RuntimeError::MemoryLimitExceeded(span, _) => span,
+            RuntimeError::IterationCapExceeded(span, _) => span,
+            RuntimeError::CallDepthExceeded(span, _) => span,
+            RuntimeError::AssertionFailed(span, _) => span,
+            RuntimeError::InvalidArgument(span, _) => span,
+            RuntimeError::InvalidOperand(span, _, _, _) => span,
         }
     }
 }
@@ -58,10 +83,42 @@ impl Display for RuntimeError {
                 "Invalid argument count (expected {}, got {}",
                 expected, actual
             ),
-            RuntimeError::Return(_) => "Return outside function".to_string(),
+            RuntimeError::Return(val) => format!("Return of '{}' outside function", val.value),
+            RuntimeError::BreakSignal(_) => "Break outside loop".to_string(),
+            RuntimeError::ContinueSignal(_) => "Continue outside loop".to_string(),
             RuntimeError::GetOnNonObject(val) => format!("Value '{}' is not an object", val.value),
-            Self::UndefinedProperty(obj, ident) => {
-                format!("Property {} does not exist on {}", ident, obj)
+            Self::UndefinedProperty(class, ident) => {
+                format!(
+                    "Property {} does not exist on an instance of {}",
+                    ident, class
+                )
+            }
+            RuntimeError::UnhashableValue(val) => {
+                format!("Value '{}' cannot be used as a key", val.value)
+            }
+            RuntimeError::MemoryLimitExceeded(_, limit) => {
+                format!("Memory limit of {} bytes exceeded", limit)
+            }
+            RuntimeError::IterationCapExceeded(_, cap) => {
+                format!("Loop exceeded the maximum of {} iterations", cap)
+            }
+            RuntimeError::CallDepthExceeded(_, depth) => {
+                format!("Calls exceeded the maximum depth of {}", depth)
+            }
+            RuntimeError::AssertionFailed(_, source) => format!("Assertion failed: {}", source),
+            RuntimeError::InvalidArgument(_, message) => format!("Invalid argument: {}", message),
+            RuntimeError::InvalidOperand(_, operator, actual, expected) => {
+                let mut expected = expected
+                    .iter()
+                    .map(|t| format!("{:?}", t))
+                    .collect::<Vec<_>>();
+                expected.sort();
+                format!(
+                    "Operator '{}' expected {}, got {:?}",
+                    operator,
+                    expected.join(" or "),
+                    actual
+                )
             }
         };
         write!(f, "{}: {}", self.location(), error_type)
@@ -1,68 +1,154 @@
 use std::fmt::Write;
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 use crate::{
     ast::{
         declarations::{ClassDeclaration, FunctionDeclaration, VariableDeclaration},
         expressions::{Expression, ExpressionNode, ExpressionVisitor},
-        statements::{Conditional, ForLoop, Statement, WhileLoop},
-        types::ValueType,
+        statements::{Assert, Conditional, ForIn, Print, Statement, StatementVisitor, WhileLoop},
+        types::{Closure, Type, ValueType},
     },
     eval::{self, runtime_error::RuntimeError, Evaluator},
-    StatementVisitor,
 };
 
+/// Returns the elements a `for-in` loop visits over a value, or `None` if the
+/// value cannot be iterated. Strings yield their characters, and objects the
+/// names of their properties in sorted order.
+fn iterate(value: &ValueType) -> Option<Box<dyn Iterator<Item = ValueType>>> {
+    match value {
+        ValueType::String(s) => Some(Box::new(
+            s.chars()
+                .map(|c| ValueType::String(Rc::new(c.to_string())))
+                .collect::<Vec<_>>()
+                .into_iter(),
+        )),
+        ValueType::Object(object) => {
+            let mut names = object
+                .borrow()
+                .properties
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>();
+            names.sort();
+            Some(Box::new(
+                names
+                    .into_iter()
+                    .map(|name| ValueType::String(Rc::new(name))),
+            ))
+        }
+        _ => None,
+    }
+}
+
+impl Evaluator {
+    /// Returns the methods of a class, each capturing the current scopes.
+    fn methods(&self, decl: &ClassDeclaration) -> HashMap<String, Rc<Closure>> {
+        decl.methods
+            .iter()
+            .map(|method| {
+                let closure = Closure {
+                    function: method.function.clone(),
+                    scopes: self.env.capture(),
+                };
+                (method.name.ident.clone(), Rc::new(closure))
+            })
+            .collect()
+    }
+}
+
 impl StatementVisitor for Evaluator {
     type Return = eval::Result<()>;
 
     fn visit_statement(&mut self, stmt: &Statement) -> Self::Return {
         match stmt {
-            Statement::Print(expr) => self.visit_print(expr),
+            Statement::Print(print) => self.visit_print(print),
+            Statement::Assert(assert) => self.visit_assert(assert),
             Statement::Expression(expr) => expr.accept(self).map(|_| ()),
             Statement::VariableDeclaration(declaration) => {
                 self.visit_variable_declaration(declaration)
             }
             Statement::ClassDeclaration(decl) => self.visit_class_declaration(decl),
             Statement::Block(stmts) => {
-                self.env.push_env();
-                for stmt in &stmts.stmts {
-                    self.visit_statement(stmt)?;
-                }
-                self.env.pop_env();
-                Ok(())
+                let mut scope = self.scope();
+                stmts
+                    .stmts
+                    .iter()
+                    .try_for_each(|stmt| scope.visit_statement(stmt))
             }
             Statement::Conditional(c) => self.visit_conditional(c),
             Statement::WhileLoop(w) => self.visit_while_loop(w),
-            Statement::ForLoop(f) => self.visit_for_loop(f),
+            Statement::ForIn(l) => self.visit_for_in(l),
             Statement::FunctionDeclaration(f) => self.visit_function_declaration(f),
             Statement::Return(expr) => self.visit_return(expr),
+            Statement::Break(span) => Err(RuntimeError::BreakSignal(*span)),
+            Statement::Continue(span) => Err(RuntimeError::ContinueSignal(*span)),
         }
     }
 
-    fn visit_print(&mut self, expr: &Expression) -> Self::Return {
-        let value = expr.accept(self)?;
-        write!(self.out, "{}", value).map_err(|_| RuntimeError::WriteError(expr.get_location()))?;
-        Ok(())
+    fn visit_print(&mut self, print: &Print) -> Self::Return {
+        let value = print.expr.accept(self)?;
+        write!(self.out, "{}", value).map_err(|_| RuntimeError::WriteError(print.location))?;
+        self.out
+            .flush_print()
+            .map_err(|_| RuntimeError::WriteError(print.location))
+    }
+
+    fn visit_assert(&mut self, assert: &Assert) -> Self::Return {
+        let condition = self.visit_expression(&assert.condition)?;
+        if self.truthiness(&condition)? {
+            Ok(())
+        } else {
+            Err(RuntimeError::AssertionFailed(
+                assert.condition.get_location(),
+                assert.source.clone(),
+            ))
+        }
     }
 
     fn visit_variable_declaration(&mut self, decl: &VariableDeclaration) -> Self::Return {
-        let init = self.visit_expression(&decl.initializer)?;
-        self.env.define(decl.name.ident.to_string(), init.value);
+        let init = match &decl.initializer {
+            Some(initializer) => self.visit_expression(initializer)?.value,
+            None => ValueType::Nil,
+        };
+        self.env.define(decl.name.ident.to_string(), init);
         Ok(())
     }
 
     fn visit_class_declaration(&mut self, decl: &ClassDeclaration) -> Self::Return {
-        let mut methods = HashMap::with_capacity(decl.methods.len());
-
-        for method in &decl.methods {
-            methods.insert(method.name.ident.clone(), method.function.clone());
-        }
+        let superclass = match &decl.superclass {
+            Some(name) => match self.visit_identifier(name)?.value {
+                ValueType::Class(class) => Some(class),
+                value => {
+                    return Err(RuntimeError::MismatchedTypes(
+                        name.location,
+                        value.as_type(),
+                        HashSet::from([Type::Class]),
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        let methods = match &superclass {
+            Some(superclass) => {
+                let mut scope = self.scope();
+                scope
+                    .env
+                    .define("super".to_string(), ValueType::Class(superclass.clone()));
+                scope.methods(decl)
+            }
+            None => self.methods(decl),
+        };
 
         self.env.define(
             decl.name.ident.to_string(),
             ValueType::Class(
                 crate::ast::types::Class {
                     name: decl.name.clone(),
+                    superclass,
                     methods,
                 }
                 .into(),
@@ -73,7 +159,7 @@ impl StatementVisitor for Evaluator {
 
     fn visit_conditional(&mut self, cond: &Conditional) -> Self::Return {
         let value = self.visit_expression(&cond.condition)?;
-        if eval::is_truthy(&value.value) {
+        if self.truthiness(&value)? {
             Ok(self.visit_statement(&cond.then_statement)?)
         } else if let Some(else_statement) = &cond.else_statement {
             Ok(self.visit_statement(else_statement)?)
@@ -83,42 +169,75 @@ impl StatementVisitor for Evaluator {
     }
 
     fn visit_while_loop(&mut self, while_loop: &WhileLoop) -> Self::Return {
-        while eval::is_truthy(&self.visit_expression(&while_loop.condition)?.value) {
-            self.visit_statement(&while_loop.statement)?;
+        let mut iterations = 0;
+        loop {
+            let condition = self.visit_expression(&while_loop.condition)?;
+            if !self.truthiness(&condition)? {
+                break;
+            }
+            iterations += 1;
+            match self.options.max_iterations {
+                Some(cap) if iterations > cap => {
+                    return Err(RuntimeError::IterationCapExceeded(
+                        while_loop.condition.get_location(),
+                        cap,
+                    ))
+                }
+                _ => (),
+            }
+            // A continue skips the rest of the statement, but not the
+            // increment of a for loop.
+            match self.visit_statement(&while_loop.statement) {
+                Ok(()) | Err(RuntimeError::ContinueSignal(_)) => (),
+                Err(RuntimeError::BreakSignal(_)) => return Ok(()),
+                Err(err) => return Err(err),
+            }
+            if let Some(increment) = &while_loop.increment {
+                self.visit_expression(increment)?;
+            }
         }
 
-        Ok(())
+        match &while_loop.else_statement {
+            Some(else_statement) => self.visit_statement(else_statement),
+            None => Ok(()),
+        }
     }
 
-    fn visit_for_loop(&mut self, for_loop: &ForLoop) -> Self::Return {
-        self.env.push_env();
-        if let Some(initializer) = &for_loop.initializer {
-            self.visit_statement(initializer)?;
-        }
-        if let Some(condition) = &for_loop.condition {
-            while eval::is_truthy(&self.visit_expression(condition)?.value) {
-                self.visit_statement(&for_loop.body)?;
-                if let Some(increment) = &for_loop.increment {
-                    self.visit_expression(increment)?;
-                }
-            }
-        } else {
-            loop {
-                self.visit_statement(&for_loop.body)?;
-                if let Some(increment) = &for_loop.increment {
-                    self.visit_expression(increment)?;
+    fn visit_for_in(&mut self, for_in: &ForIn) -> Self::Return {
+        let iterable = self.visit_expression(&for_in.iterable)?;
+        let elements = iterate(&iterable.value).ok_or_else(|| {
+            RuntimeError::MismatchedTypes(
+                iterable.location,
+                iterable.value.as_type(),
+                HashSet::from([Type::Object, Type::String]),
+            )
+        })?;
+
+        {
+            let mut scope = self.scope();
+            for element in elements {
+                scope.env.define(for_in.variable.ident.clone(), element);
+                match scope.visit_statement(&for_in.statement) {
+                    Ok(()) | Err(RuntimeError::ContinueSignal(_)) => (),
+                    Err(RuntimeError::BreakSignal(_)) => return Ok(()),
+                    Err(err) => return Err(err),
                 }
             }
         }
-        self.env.pop_env();
 
-        Ok(())
+        match &for_in.else_statement {
+            Some(else_statement) => self.visit_statement(else_statement),
+            None => Ok(()),
+        }
     }
 
     fn visit_function_declaration(&mut self, fd: &FunctionDeclaration) -> Self::Return {
         self.env.define(
             fd.name.ident.to_string(),
-            ValueType::Function(fd.function.clone()),
+            ValueType::Function(Rc::new(Closure {
+                function: fd.function.clone(),
+                scopes: self.env.capture(),
+            })),
         );
 
         Ok(())
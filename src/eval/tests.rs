@@ -1,9 +1,16 @@
-use std::rc::Rc;
+use std::{cell::RefCell, rc::Rc};
 
 use crate::{
-    ast::{expressions::ExpressionVisitor, statements::StatementVisitor, types::ValueType},
-    eval::{builtins::test_prelude, output_stream::OutputStream, Evaluator, ValueType::*},
-    parsing::{parse, parse_expression},
+    ast::{
+        expressions::{BinaryOperator, ExpressionVisitor},
+        statements::{StatementVisitor, Statements},
+        types::ValueType,
+    },
+    eval::{
+        builtins::test_prelude, output_stream::OutputStream, Evaluator, Options, Resolver,
+        RuntimeError, ValueType::*, Warning,
+    },
+    parsing::{parse, parse_expression, parse_one_statement},
     scanning::TokenStream,
 };
 
@@ -11,7 +18,8 @@ fn assert_eval_expr(code: &str, result: ValueType) {
     let mut tokens = TokenStream::new(code);
     let tree = parse_expression(&mut tokens).unwrap();
     assert_eq!(
-        Evaluator::new(OutputStream::File(std::string::String::new()))
+        Evaluator::capturing()
+            .0
             .visit_expression(&tree)
             .unwrap()
             .value,
@@ -39,6 +47,39 @@ gen_tests_expr!(literals,
     { "false",      Boolean(false)              }
 );
 
+gen_tests_expr!(unary_plus,
+    { "+5",      Number(5.0)  },
+    { "-+5",     Number(-5.0) },
+    { "+(1 + 2)", Number(3.0) }
+);
+
+#[test]
+fn unary_plus_on_non_numbers() {
+    let mut evaluator = Evaluator::capturing().0;
+    for code in ["+\"x\"", "+nil", "+true"] {
+        let expr = parse_expression(&mut TokenStream::new(code)).unwrap();
+        assert!(matches!(
+            evaluator.visit_expression(&expr),
+            Err(RuntimeError::MismatchedTypes(..))
+        ));
+    }
+}
+
+#[test]
+fn string_literals_are_shared() {
+    let literal = parse_expression(&mut TokenStream::new("\"abc\"")).unwrap();
+    let mut evaluator = Evaluator::capturing().0;
+    let first = evaluator.visit_expression(&literal).unwrap().value;
+    let second = evaluator.visit_expression(&literal).unwrap().value;
+    match (first, second) {
+        (String(first), String(second)) => {
+            assert!(Rc::ptr_eq(&first, &second));
+            assert_eq!(Rc::strong_count(&first), 3);
+        }
+        values => panic!("expected strings, got {:?}", values),
+    }
+}
+
 gen_tests_expr!(unary,
     { "-3",          Number(-3.0)   },
     { "--3",         Number(3.0)    },
@@ -63,9 +104,45 @@ gen_tests_expr!(arithmetic_binary_operators,
     { "2 - 2", Number(0.0) },
     { "2 * 2", Number(4.0) },
     { "2 / 2", Number(1.0) },
-    { "0 / 1", Number(0.0) }
+    { "0 / 1", Number(0.0) },
+    { "5 % 3", Number(2.0) },
+    { "-5 % 3", Number(-2.0) },
+    { "5.5 % 2", Number(1.5) },
+    { "1 + 5 % 3 * 2", Number(5.0) },
+    { "5 % 3 == 2", Boolean(true) }
 );
 
+#[test]
+fn modulo_by_zero() {
+    let mut evaluator = Evaluator::capturing().0;
+    let expr = parse_expression(&mut TokenStream::new("5 % 0")).unwrap();
+    assert!(matches!(
+        evaluator.visit_expression(&expr),
+        Err(RuntimeError::DivisionByZero(..))
+    ));
+}
+
+#[test]
+fn division_by_computed_zero() {
+    let mut evaluator = Evaluator::capturing().0;
+    for code in ["1 / (1 - 1)", "1 / -0", "1 % -0"] {
+        let expr = parse_expression(&mut TokenStream::new(code)).unwrap();
+        assert!(
+            matches!(
+                evaluator.visit_expression(&expr),
+                Err(RuntimeError::DivisionByZero(..))
+            ),
+            "{}",
+            code
+        );
+    }
+    let expr = parse_expression(&mut TokenStream::new("1 / \"x\"")).unwrap();
+    assert!(matches!(
+        evaluator.visit_expression(&expr),
+        Err(RuntimeError::InvalidOperand(..))
+    ));
+}
+
 gen_tests_expr!(comparison_binary_operators,
     { "1 < 0",  Boolean(false) },
     { "1 < 1",  Boolean(false) },
@@ -120,16 +197,322 @@ gen_tests_expr!(complex_expressions,
 
 fn assert_eval_stmts(code: &str, expected: &str) {
     let statements = parse(&mut TokenStream::new(code)).unwrap();
-    let mut evaluator = Evaluator::new(OutputStream::File(std::string::String::new()));
+    let (mut evaluator, output) = Evaluator::capturing();
     evaluator.register_prelude(test_prelude());
     for stmt in &statements.stmts {
-        evaluator.visit_statement(&stmt).unwrap();
+        evaluator.visit_statement(stmt).unwrap();
+    }
+    assert_eq!(*output.borrow(), expected);
+}
+
+fn eval_warnings(code: &str, options: Options) -> Vec<Warning> {
+    let statements = parse(&mut TokenStream::new(code)).unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    *evaluator.options_mut() = options;
+    for stmt in &statements.stmts {
+        evaluator.visit_statement(stmt).unwrap();
     }
-    if let OutputStream::File(s) = &evaluator.out {
-        assert_eq!(s, expected);
-    } else {
-        assert!(false, "OutputStream is not a String !");
+    evaluator.take_warnings()
+}
+
+#[test]
+fn precision_warnings() {
+    let options = Options {
+        precision_warnings: true,
+        ..Options::default()
+    };
+    let warnings = eval_warnings("print 123456789 * 987654321;", options.clone());
+    assert_eq!(warnings.len(), 1);
+    assert!(matches!(
+        warnings[0],
+        Warning::PrecisionLoss(_, BinaryOperator::Multiplication, _)
+    ));
+    assert!(eval_warnings("print 9007199254740990 + 1;", options.clone()).is_empty());
+    assert!(eval_warnings("print 123456789.5 * 987654321;", options).is_empty());
+}
+
+#[test]
+fn precision_warnings_disabled() {
+    assert!(eval_warnings("print 123456789 * 987654321;", Options::default()).is_empty());
+}
+
+fn eval_with_options(code: &str, options: Options) -> std::string::String {
+    let statements = parse(&mut TokenStream::new(code)).unwrap();
+    let (mut evaluator, output) = Evaluator::capturing();
+    *evaluator.options_mut() = options;
+    for stmt in &statements.stmts {
+        evaluator.visit_statement(stmt).unwrap();
     }
+    let output = output.borrow().clone();
+    output
+}
+
+#[test]
+fn float_tolerance() {
+    let code = "print 0.1 + 0.2 == 0.3; print 0.1 + 0.2 != 0.3; print 1 == 1.1;";
+    assert_eq!(
+        eval_with_options(code, Options::default()),
+        "falsetruefalse"
+    );
+    let options = Options {
+        float_tolerance: Some(1e-9),
+        ..Options::default()
+    };
+    assert_eq!(eval_with_options(code, options), "truefalsefalse");
+}
+
+#[test]
+fn strict_equality() {
+    let code = "print 1 == \"1\"; print 1 != \"1\"; print 1 == 1;";
+    assert_eq!(eval_with_options(code, Options::default()), "falsetruetrue");
+
+    let mut evaluator = Evaluator::capturing().0;
+    evaluator.options_mut().strict_equality = true;
+    for code in ["1 == \"1\";", "nil != false;"] {
+        let stmt = parse_one_statement(code).unwrap();
+        assert!(matches!(
+            evaluator.visit_statement(&stmt),
+            Err(RuntimeError::InvalidOperand(..))
+        ));
+    }
+    let options = Options {
+        strict_equality: true,
+        ..Options::default()
+    };
+    assert_eq!(
+        eval_with_options("print 1 == 1; print \"a\" != \"b\";", options),
+        "truetrue"
+    );
+}
+
+#[test]
+fn memory_limit() {
+    let code = "var s = \"abcd\"; while (true) s = s + s;";
+    let statements = parse(&mut TokenStream::new(code)).unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    evaluator.options_mut().memory_limit = Some(1024);
+    evaluator.visit_statement(&statements.stmts[0]).unwrap();
+    let error = evaluator.visit_statement(&statements.stmts[1]).unwrap_err();
+    assert!(matches!(error, RuntimeError::MemoryLimitExceeded(_, 1024)));
+
+    let code = "var s = \"abcd\"; for (var i = 0; i < 8; i = i + 1) s = s + s; print s == s;";
+    assert_eq!(eval_with_options(code, Options::default()), "true");
+}
+
+#[test]
+fn memory_limit_counts_appended_bytes() {
+    let options = Options {
+        memory_limit: Some(20000),
+        ..Options::default()
+    };
+    let code = "var s = \"a\" + \"b\"; for (var i = 0; i < 10000; i = i + 1) s = s + \"c\"; \
+        print s == \"ab\";";
+    assert_eq!(eval_with_options(code, options), "false");
+}
+
+#[test]
+fn memory_limit_counts_natives() {
+    let code = "var s; for (var i = 0; i < 1000; i = i + 1) s = pad_left(\"\", 4096);";
+    let statements = parse(&mut TokenStream::new(code)).unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    evaluator.register_prelude(test_prelude());
+    evaluator.options_mut().memory_limit = Some(1 << 20);
+    evaluator.visit_statement(&statements.stmts[0]).unwrap();
+    let error = evaluator.visit_statement(&statements.stmts[1]).unwrap_err();
+    assert!(matches!(error, RuntimeError::MemoryLimitExceeded(_, _)));
+}
+
+#[test]
+fn iteration_cap() {
+    let code = "while (true) {}";
+    let statements = parse(&mut TokenStream::new(code)).unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    evaluator.options_mut().max_iterations = Some(100);
+    let error = evaluator.visit_statement(&statements.stmts[0]).unwrap_err();
+    assert!(matches!(error, RuntimeError::IterationCapExceeded(_, 100)));
+    assert_eq!(
+        error.to_string(),
+        "[1,7]-[1,11]: Loop exceeded the maximum of 100 iterations"
+    );
+
+    let options = Options {
+        max_iterations: Some(3),
+        ..Options::default()
+    };
+    let code = "for (var i = 0; i < 3; i = i + 1) print i;";
+    assert_eq!(eval_with_options(code, options), "012");
+
+    let code = "for (;;) {}";
+    let statements = parse(&mut TokenStream::new(code)).unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    evaluator.options_mut().max_iterations = Some(100);
+    let error = evaluator.visit_statement(&statements.stmts[0]).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "[1,0]-[1,3]: Loop exceeded the maximum of 100 iterations"
+    );
+}
+
+#[test]
+fn call_depth_limit() {
+    let code = "fun f(n) { if (n > 0) return f(n - 1); return n; } f(100);";
+    let statements = parse(&mut TokenStream::new(code)).unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    evaluator.options_mut().max_call_depth = Some(50);
+    evaluator.visit_statement(&statements.stmts[0]).unwrap();
+    let error = evaluator.visit_statement(&statements.stmts[1]).unwrap_err();
+    assert!(matches!(error, RuntimeError::CallDepthExceeded(_, 50)));
+
+    let options = Options {
+        max_call_depth: Some(50),
+        ..Options::default()
+    };
+    let code = "fun f(n) { if (n > 0) return f(n - 1); return n; } print f(49); print f(49);";
+    assert_eq!(eval_with_options(code, options), "00");
+}
+
+#[test]
+fn assert_failure_shows_condition() {
+    let code = "var x = 0;\nassert(x  > 0);";
+    let statements = parse(&mut TokenStream::new(code)).unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    evaluator.visit_statement(&statements.stmts[0]).unwrap();
+    let error = evaluator.visit_statement(&statements.stmts[1]).unwrap_err();
+    assert!(matches!(&error, RuntimeError::AssertionFailed(_, source) if source == "x  > 0"));
+    assert_eq!(error.to_string(), "[2,7]-[2,13]: Assertion failed: x  > 0");
+}
+
+#[test]
+fn statements_builder() {
+    let mut program = Statements::new();
+    program.push(parse_one_statement("var a = 1;").unwrap());
+    program.extend(
+        ["print a;", "a = a + 1;", "print a;"].map(|code| parse_one_statement(code).unwrap()),
+    );
+    assert_eq!(
+        program.to_string(),
+        "var a = 1;\nprint a;\na = a + 1;\nprint a;\n"
+    );
+
+    let (mut evaluator, output) = Evaluator::capturing();
+    for stmt in &program.stmts {
+        evaluator.visit_statement(stmt).unwrap();
+    }
+    assert_eq!(*output.borrow(), "12");
+}
+
+#[test]
+fn capturing_output_between_statements() {
+    let statements = parse(&mut TokenStream::new("print 1; print 2;")).unwrap();
+    let (mut evaluator, output) = Evaluator::capturing();
+    evaluator.visit_statement(&statements.stmts[0]).unwrap();
+    assert_eq!(*output.borrow(), "1");
+    output.borrow_mut().clear();
+    evaluator.visit_statement(&statements.stmts[1]).unwrap();
+    assert_eq!(*output.borrow(), "2");
+}
+
+#[test]
+fn replacing_output() {
+    let statements = parse(&mut TokenStream::new("print 1; print 2;")).unwrap();
+    let (mut evaluator, first) = Evaluator::capturing();
+    evaluator.visit_statement(&statements.stmts[0]).unwrap();
+    let second = Rc::new(RefCell::new(std::string::String::new()));
+    let previous = evaluator.set_output(OutputStream::Buffer(second.clone()));
+    assert!(matches!(previous, OutputStream::Buffer(buffer) if Rc::ptr_eq(&buffer, &first)));
+    evaluator.visit_statement(&statements.stmts[1]).unwrap();
+    assert_eq!(*first.borrow(), "1");
+    assert_eq!(*second.borrow(), "2");
+}
+
+#[test]
+fn scope_is_popped_on_error() {
+    let code = "fun f() { var x = 1; -nil; } f(); print x;";
+    let statements = parse(&mut TokenStream::new(code)).unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    evaluator.visit_statement(&statements.stmts[0]).unwrap();
+    assert!(evaluator.visit_statement(&statements.stmts[1]).is_err());
+    let error = evaluator.visit_statement(&statements.stmts[2]).unwrap_err();
+    assert!(matches!(error, RuntimeError::UnboundName(_, name) if name == "x"));
+}
+
+#[test]
+fn print_does_not_flush_writers() {
+    struct FlushCounter {
+        written: Rc<RefCell<Vec<u8>>>,
+        flushes: Rc<RefCell<usize>>,
+    }
+
+    impl std::io::Write for FlushCounter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            *self.flushes.borrow_mut() += 1;
+            Ok(())
+        }
+    }
+
+    let written = Rc::new(RefCell::new(Vec::new()));
+    let flushes = Rc::new(RefCell::new(0));
+    let mut evaluator = Evaluator::new(OutputStream::Writer(Box::new(FlushCounter {
+        written: written.clone(),
+        flushes: flushes.clone(),
+    })));
+    let statements = parse(&mut TokenStream::new("print 1; 2; print 3;")).unwrap();
+    for stmt in &statements.stmts {
+        evaluator.visit_statement(stmt).unwrap();
+    }
+    assert_eq!(*written.borrow(), b"13");
+    assert_eq!(*flushes.borrow(), 0);
+    let mut out = evaluator.set_output(OutputStream::Buffer(Default::default()));
+    out.flush().unwrap();
+    assert_eq!(*flushes.borrow(), 1);
+}
+
+#[test]
+fn write_error_points_at_print_statement() {
+    struct FailingWriter;
+
+    impl std::io::Write for FailingWriter {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut evaluator = Evaluator::new(OutputStream::Writer(Box::new(FailingWriter)));
+    let stmt = parse_one_statement("print  1 + 2 ;").unwrap();
+    let error = evaluator.visit_statement(&stmt).unwrap_err();
+    assert_eq!(error.to_string(), "[1,0]-[1,14]: Write failed");
+}
+
+#[test]
+fn leaked_bare_return_points_at_keyword() {
+    let statements = parse(&mut TokenStream::new("print 1;\n  return;")).unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    evaluator.visit_statement(&statements.stmts[0]).unwrap();
+    let error = evaluator.visit_statement(&statements.stmts[1]).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "[2,2]-[2,8]: Return of 'nil' outside function"
+    );
+}
+
+#[test]
+fn leaked_return_shows_value() {
+    let stmt = parse_one_statement("return 5;").unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    let error = evaluator.visit_statement(&stmt).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "[1,7]-[1,8]: Return of '5' outside function"
+    );
 }
 
 macro_rules! gen_tests {
@@ -253,12 +636,105 @@ gen_tests!(
     "0123456789"
 );
 
+gen_tests!(
+    for_loop_same_as_while,
+    "for (var i = 0; i < 10; i = i + 1) print i; \
+    { var i = 0; while (i < 10) { print i; i = i + 1; } }",
+    "01234567890123456789"
+);
+
+#[test]
+fn for_loop_variable_is_scoped() {
+    let statements = parse(&mut TokenStream::new(
+        "for (var i = 0; i < 1; i = i + 1) {} print i;",
+    ))
+    .unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    evaluator.visit_statement(&statements.stmts[0]).unwrap();
+    assert!(matches!(
+        evaluator.visit_statement(&statements.stmts[1]),
+        Err(crate::eval::RuntimeError::UnboundName(..))
+    ));
+}
+
 gen_tests!(
     for_loop_omitted_fields,
     "var i = 0; for (;i < 10;) { print i; i = i + 1; }",
     "0123456789"
 );
 
+gen_tests!(
+    while_loop_break,
+    "var i = 0; while (true) { if (i == 3) break; print i; i = i + 1; }",
+    "012"
+);
+
+gen_tests!(
+    for_loop_break,
+    "for (var i = 0; i < 10; i = i + 1) { if (i == 3) break; print i; }",
+    "012"
+);
+
+gen_tests!(
+    for_loop_continue_runs_increment,
+    "for (var i = 0; i < 5; i = i + 1) { if (i == 1 or i == 3) continue; print i; }",
+    "024"
+);
+
+gen_tests!(
+    while_loop_continue_checks_condition,
+    "var i = 0; while (i < 5) { i = i + 1; if (i == 2) continue; print i; }",
+    "1345"
+);
+
+gen_tests!(
+    for_in_continue,
+    "for (var c in \"abc\") { if (c == \"b\") continue; print c; }",
+    "ac"
+);
+
+gen_tests!(
+    while_loop_else_completed,
+    "var i = 0; while (i < 3) i = i + 1; else print \"done\";",
+    "done"
+);
+
+gen_tests!(
+    while_loop_else_after_break,
+    "while (true) break; else print \"done\"; print \"after\";",
+    "after"
+);
+
+gen_tests!(
+    for_loop_else_completed,
+    "for (var i = 0; i < 3; i = i + 1) print i; else print \"done\";",
+    "012done"
+);
+
+gen_tests!(
+    for_loop_else_after_break,
+    "for (var i = 0; i < 3; i = i + 1) { if (i == 1) break; print i; } else print \"done\";",
+    "0"
+);
+
+gen_tests!(
+    for_in_else_completed,
+    "for (var c in \"ab\") print c; else print \"done\";",
+    "abdone"
+);
+
+gen_tests!(
+    for_in_else_after_break,
+    "for (var c in \"ab\") { print c; break; } else print \"done\";",
+    "a"
+);
+
+gen_tests!(
+    if_else_after_loop,
+    "var c = false; if (c) while (false) print \"loop\"; else print \"if-else\";",
+    "if-else"
+);
+
 gen_tests!(
     simple_fibonacci,
     "\
@@ -274,8 +750,156 @@ for (var b = 1; a < 10000; b = temp + b) {
     "011235813213455891442333776109871597258441816765"
 );
 
+gen_tests!(
+    string_escapes,
+    r#"print "a\nb"; print "\t\"quoted\"\\";"#,
+    "a\nb\t\"quoted\"\\"
+);
+
 gen_tests!(native_function, r#"print hello("Hugo");"#, "Hello, Hugo");
 
+gen_tests!(
+    padding,
+    r#"print "[" + pad_left(42, 5) + "]"; print "[" + pad_right("ab", 4) + "]";
+    print "[" + pad_left("abcdef", 3) + "]"; print "[" + pad_right(1.5, 0) + "]";"#,
+    "[   42][ab  ][abcdef][1.5]"
+);
+
+gen_tests!(
+    sprint,
+    r#"var s = sprint(1, " ", true); print s == "1 true"; print sprint();
+    print sprint(nil, 2.5, "!");"#,
+    "truenil2.5!"
+);
+
+gen_tests!(
+    to_json,
+    "class Point {} var p = Point(); p.x = 1; p.name = \"a\\\"b\"; print to_json(p);",
+    r#"{"name":"a\"b","x":1}"#
+);
+
+gen_tests!(
+    from_json,
+    r#"var data = from_json("{\"name\": \"rlox\", \"meta\": {\"version\": 1.5, \"stable\": false}}");
+    print data.name; print data.meta.version; print data.meta.stable;"#,
+    "rlox1.5false"
+);
+
+#[test]
+fn from_json_errors() {
+    let mut evaluator = Evaluator::capturing().0;
+    evaluator.register_prelude(test_prelude());
+    for (code, expected) in [
+        ("from_json(\"{\\\"a\\\": }\");", "Invalid argument"),
+        ("from_json(\"[1, 2]\");", "Invalid argument"),
+        ("from_json(1);", "Mismatched Type"),
+    ] {
+        let stmt = parse_one_statement(code).unwrap();
+        let error = evaluator.visit_statement(&stmt).unwrap_err();
+        assert!(error.to_string().contains(expected), "{}", error);
+    }
+}
+
+#[test]
+fn call_from_host() {
+    let code = "fun add(a, b) { print a; return a + b; } \
+        class Point {}";
+    let (mut evaluator, output) = Evaluator::capturing();
+    evaluator.register_prelude(test_prelude());
+    for stmt in parse(&mut TokenStream::new(code)).unwrap().stmts {
+        evaluator.visit_statement(&stmt).unwrap();
+    }
+
+    let add = evaluator.get("add").unwrap();
+    let sum = evaluator.call(add, vec![Number(1.0), Number(2.0)]).unwrap();
+    assert_eq!(sum, Number(3.0));
+    assert_eq!(*output.borrow(), "1");
+
+    let point = evaluator.get("Point").unwrap();
+    match evaluator.call(point, vec![]).unwrap() {
+        ValueType::Object(point) => assert_eq!(point.borrow().class.name.ident, "Point"),
+        value => panic!("expected an object, got {:?}", value),
+    }
+
+    let hello = evaluator.get("hello").unwrap();
+    assert_eq!(
+        evaluator
+            .call(hello, vec![String(Rc::new("host".to_string()))])
+            .unwrap(),
+        String(Rc::new("Hello, host".to_string()))
+    );
+
+    let add = evaluator.get("add").unwrap();
+    assert!(matches!(
+        evaluator.call(add, vec![]),
+        Err(RuntimeError::InvalidArgumentCount(_, 2, 0))
+    ));
+    assert!(matches!(
+        evaluator.call(Number(1.0), vec![]),
+        Err(RuntimeError::NotCallable(_))
+    ));
+    assert!(evaluator.get("missing").is_none());
+}
+
+#[test]
+fn prelude_subset() {
+    let (mut evaluator, output) = Evaluator::capturing();
+    evaluator.register_prelude_subset(test_prelude(), &["pad_left", "hello"]);
+    let stmt = parse_one_statement("print pad_left(hello(\"x\"), 9);").unwrap();
+    evaluator.visit_statement(&stmt).unwrap();
+    assert_eq!(*output.borrow(), " Hello, x");
+    for code in ["clock();", "pad_right(1, 2);"] {
+        let stmt = parse_one_statement(code).unwrap();
+        assert!(matches!(
+            evaluator.visit_statement(&stmt),
+            Err(RuntimeError::UnboundName(..))
+        ));
+    }
+}
+
+#[test]
+fn padding_errors() {
+    let mut evaluator = Evaluator::capturing().0;
+    evaluator.register_prelude(test_prelude());
+    for (code, expected) in [
+        ("pad_left(1, -1);", "Invalid argument"),
+        ("pad_right(1, 2.5);", "Invalid argument"),
+        ("pad_left(1, 1000000000000);", "width must be at most 4096"),
+        ("pad_left(1, \"2\");", "Mismatched Type"),
+    ] {
+        let stmt = parse_one_statement(code).unwrap();
+        let error = evaluator.visit_statement(&stmt).unwrap_err();
+        assert!(error.to_string().contains(expected), "{}", error);
+    }
+}
+
+#[test]
+fn operand_errors_name_the_operator() {
+    let mut evaluator = Evaluator::capturing().0;
+    for (code, expected) in [
+        (
+            "1 - nil;",
+            "[1,4]-[1,7]: Operator '-' expected Number, got Nil",
+        ),
+        (
+            "\"a\" * 2;",
+            "[1,0]-[1,3]: Operator '*' expected Number, got String",
+        ),
+        (
+            "true < 1;",
+            "[1,0]-[1,4]: Operator '<' expected Number, got Boolean",
+        ),
+        (
+            "nil + 1;",
+            "[1,0]-[1,3]: Operator '+' expected Number or String, got Nil",
+        ),
+    ] {
+        let stmt = parse_one_statement(code).unwrap();
+        let error = evaluator.visit_statement(&stmt).unwrap_err();
+        assert_eq!(error.to_string(), expected);
+    }
+}
+
 gen_tests!(function_declaration_simple, "fun a() { }", "");
 
 gen_tests!(function_declaration_args, "fun a(a, b, c) { }", "");
@@ -305,18 +929,291 @@ gen_tests!(class_print, "class MyClass {} print MyClass;", "MyClass");
 gen_tests!(
     object_print,
     "class MyClass {} print MyClass();",
-    "MyClass instance"
+    "MyClass {}"
 );
 
+gen_tests!(
+    object_print_fields,
+    "class Point {} var p = Point(); p.y = 2; p.x = \"one\"; print p;",
+    "Point { x: one, y: 2 }"
+);
+
+gen_tests!(
+    object_print_cycles,
+    "class A {} class B {} var a = A(); var b = B(); a.me = a; a.b = b; b.a = a; b.other = B(); \
+    print a; print b;",
+    "A { b: B { a: A {...}, other: B {} }, me: A {...} }B { a: A { b: B {...}, me: A {...} }, other: B {} }"
+);
+
+gen_tests!(
+    object_set_nested_field,
+    "class Node {} var a = Node(); a.b = Node(); var c = Node(); \
+    a.b.c = c.d = 1; print a.b.c; print c.d; a.b.c = a.b.c + 1; print a.b.c;",
+    "112"
+);
+
+gen_tests!(
+    assert_passes,
+    "var x = 1; assert x > 0; assert(\"non-empty\"); print x;",
+    "1"
+);
+
+gen_tests!(
+    for_in_object_fields,
+    "class Point {} var p = Point(); p.y = 2; p.x = 1; \
+    for (var key in p) { if (key == \"z\") break; print key; }",
+    "xy"
+);
+
+gen_tests!(
+    for_in_string_characters,
+    "var s = \"\"; for (var c in \"abc\") s = c + s; print s; for (var c in \"\") print c;",
+    "cba"
+);
+
+#[test]
+fn for_in_non_iterable() {
+    let mut evaluator = Evaluator::capturing().0;
+    let stmt = parse_one_statement("for (var x in 1) print x;").unwrap();
+    assert!(matches!(
+        evaluator.visit_statement(&stmt),
+        Err(RuntimeError::MismatchedTypes(
+            _,
+            crate::ast::types::Type::Number,
+            _
+        ))
+    ));
+}
+
 gen_tests!(
     object_get_set,
     "class MyClass {} var o = MyClass(); o.p = 3; print o.p;",
     "3"
 );
 
-gen_tests!(object_methods, "class MyClass { method() { print 2; } } MyClass().method();", "2");
+#[test]
+fn object_get_errors() {
+    let mut evaluator = Evaluator::capturing().0;
+    let stmt = parse_one_statement("class A {}").unwrap();
+    evaluator.visit_statement(&stmt).unwrap();
+    let stmt = parse_one_statement("A().missing;").unwrap();
+    assert!(matches!(
+        evaluator.visit_statement(&stmt),
+        Err(RuntimeError::UndefinedProperty(_, name)) if name.ident == "missing"
+    ));
+    let stmt = parse_one_statement("{ var a = A(); a.me = a; a.missing; }").unwrap();
+    assert_eq!(
+        evaluator.visit_statement(&stmt).unwrap_err().to_string(),
+        "[1,27]-[1,34]: Property missing does not exist on an instance of A"
+    );
+    let stmt = parse_one_statement("(1).field;").unwrap();
+    assert!(matches!(
+        evaluator.visit_statement(&stmt),
+        Err(RuntimeError::GetOnNonObject(value)) if value.value == Number(1.0)
+    ));
+}
+
+gen_tests!(
+    object_equality_is_identity,
+    "class C {} var a = C(); var b = a; var c = C(); c.x = 1; var d = C(); d.x = 1; \
+    print a == b; print a != b; print a == c; print c == d; print C() == C(); print a == nil;",
+    "truefalsefalsefalsefalsefalse"
+);
+
+gen_tests!(
+    object_methods,
+    "class MyClass { method() { print 2; } } MyClass().method();",
+    "2"
+);
+
+gen_tests!(
+    objects_fields_over_methods,
+    "class MyClass { method() { print 2; } } var v = MyClass(); v.method = 1; print v.method;",
+    "1"
+);
+
+gen_tests!(
+    object_this,
+    "class Counter { incr() { this.n = this.n + 1; return this.n; } } \
+    var c = Counter(); c.n = 0; c.incr(); print c.incr();",
+    "2"
+);
+
+gen_tests!(
+    object_this_nested_calls,
+    "class Greeter { greet() { return this.prefix() + this.name; } prefix() { return \"Hi \"; } } \
+    var g = Greeter(); g.name = \"Bob\"; print g.greet();",
+    "Hi Bob"
+);
+
+gen_tests!(
+    object_bound_method,
+    "class A { get() { return this.v; } } var a = A(); a.v = 1; var m = a.get; a.v = 2; print m();",
+    "2"
+);
+
+gen_tests!(
+    object_truthiness,
+    "class Box { __bool__() { return this.full; } } var b = Box(); \
+    b.full = false; if (b) print 1; else print 2; print !b; print b or 3; \
+    b.full = true; while (b) { print 4; b.full = false; } print !(b and 5);",
+    "2true34true"
+);
+
+gen_tests!(
+    object_default_truthiness,
+    "class A {} if (A()) print 1; else print 2;",
+    "1"
+);
+
+gen_tests!(
+    nested_blocks_shadowing,
+    "var a = 0; { var a = 1; { var a = 2; { var a = 3; print a; } print a; } print a; } print a;",
+    "3210"
+);
+
+#[test]
+fn callee_does_not_see_caller_locals() {
+    let statements = parse(&mut TokenStream::new(
+        "fun a() { var myvar = 1; b(); return myvar; } fun b() { myvar = 2; } print a();",
+    ))
+    .unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    evaluator.visit_statement(&statements.stmts[0]).unwrap();
+    evaluator.visit_statement(&statements.stmts[1]).unwrap();
+    assert!(matches!(
+        evaluator.visit_statement(&statements.stmts[2]),
+        Err(RuntimeError::UnboundName(_, name)) if name == "myvar"
+    ));
+}
+
+gen_tests!(
+    local_recursive_function,
+    "fun make() { fun fact(n) { if (n <= 1) return 1; return n * fact(n - 1); } return fact; } \
+    print make()(5);",
+    "120"
+);
+
+gen_tests!(
+    closure_captures_scope,
+    "fun counter() { var count = 0; fun next() { count = count + 1; return count; } return next; } \
+    var a = counter(); var b = counter(); a(); a(); b(); print a(); print b();",
+    "32"
+);
+
+gen_tests!(
+    method_captures_scope,
+    "fun make(greeting) { class A { greet() { return greeting; } } return A(); } \
+    print make(\"hi\").greet();",
+    "hi"
+);
+
+gen_tests!(
+    print_infinity,
+    "var x = 1; while (x < x * 10) x = x * 10; print x; print \" \"; print -x;",
+    "Infinity -Infinity"
+);
 
-gen_tests!(objects_fields_over_methods, "class MyClass { method() { print 2; } } var v = MyClass(); v.method = 1; print v.method;", "1");
+gen_tests!(
+    print_nan,
+    "var x = 1; while (x < x * 10) x = x * 10; print x - x;",
+    "NaN"
+);
 
-// This is because I do not do a resolving pass so environments are hopelessly borked.
-gen_tests!(rly_stupid, "fun a() { var myvar = 1; b(); return myvar; } fun b() { myvar = 2; } print a();", "2");
+#[test]
+fn resolved_closure_sees_declaration_scope() {
+    let code = "var a = \"global\"; { fun show() { print a; } show(); var a = \"block\"; show(); }";
+    let statements = parse(&mut TokenStream::new(code)).unwrap();
+    Resolver::new().resolve(&statements).unwrap();
+    let (mut evaluator, output) = Evaluator::capturing();
+    for stmt in &statements.stmts {
+        evaluator.visit_statement(stmt).unwrap();
+    }
+    assert_eq!(*output.borrow(), "globalglobal");
+}
+
+#[test]
+fn resolved_local_functions_see_later_declarations() {
+    let code = "{ fun f() { return g(); } fun g() { return 1; } print f(); } \
+        fun h() { fun a() { return b(); } fun b() { return 2; } return a(); } print h();";
+    let statements = parse(&mut TokenStream::new(code)).unwrap();
+    Resolver::new().resolve(&statements).unwrap();
+    let (mut evaluator, output) = Evaluator::capturing();
+    for stmt in &statements.stmts {
+        evaluator.visit_statement(stmt).unwrap();
+    }
+    assert_eq!(*output.borrow(), "12");
+}
+
+gen_tests!(
+    inherited_methods,
+    "class A { a() { return \"A.a\"; } b() { return \"A.b\"; } } \
+    class B < A { b() { return \"B.b\"; } } \
+    var b = B(); print b.a(); print \" \"; print b.b();",
+    "A.a B.b"
+);
+
+gen_tests!(
+    super_calls_parent_method,
+    "class A { greet() { return \"Hello, \" + this.name; } } \
+    class B < A { greet() { return super.greet() + \"!\"; } } \
+    class C < B { greet() { return \"<\" + super.greet() + \">\"; } } \
+    var c = C(); c.name = \"you\"; print c.greet();",
+    "<Hello, you!>"
+);
+
+#[test]
+fn superclass_must_be_a_class() {
+    let statements = parse(&mut TokenStream::new("var A = 1; class B < A {}")).unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    evaluator.visit_statement(&statements.stmts[0]).unwrap();
+    assert!(matches!(
+        evaluator.visit_statement(&statements.stmts[1]),
+        Err(RuntimeError::MismatchedTypes(..))
+    ));
+}
+
+gen_tests!(
+    concatenation_keeps_shared_strings,
+    "var a = \"a\"; var b = a + \"b\"; var c = a + \"b\" + \"c\" + a; \
+    print a; print \" \"; print b; print \" \"; print c;",
+    "a ab abca"
+);
+
+#[test]
+fn long_concatenation() {
+    let code =
+        "var s = \"\"; for (var i = 0; i < 10000; i = i + 1) s = s + \"ab\" + \"c\"; print s;";
+    assert_eval_stmts(code, &"abc".repeat(10000));
+}
+
+#[test]
+fn appending_to_a_variable_reuses_its_string() {
+    fn string_of(evaluator: &Evaluator) -> *const std::string::String {
+        match evaluator.env.get("s") {
+            Some(ValueType::String(s)) => Rc::as_ptr(&s),
+            value => panic!("unexpected value {:?}", value),
+        }
+    }
+    let code = "var s = \"a\" + \"b\"; s = s + \"c\"; { s = s + \"d\"; }";
+    let statements = parse(&mut TokenStream::new(code)).unwrap();
+    let (mut evaluator, _) = Evaluator::capturing();
+    evaluator.visit_statement(&statements.stmts[0]).unwrap();
+    let string = string_of(&evaluator);
+    evaluator.visit_statement(&statements.stmts[1]).unwrap();
+    evaluator.visit_statement(&statements.stmts[2]).unwrap();
+    assert_eq!(string_of(&evaluator), string);
+    assert_eq!(evaluator.env.get("s").unwrap().to_string(), "abcd");
+}
+
+#[test]
+fn copied_concatenations() {
+    let options = Options {
+        copy_concatenations: true,
+        ..Options::default()
+    };
+    let code = "var a = \"a\"; var b = a + \"b\" + \"c\"; print a; print \" \"; print b;";
+    assert_eq!(eval_with_options(code, options.clone()), "a abc");
+    let code = "var s = \"\"; for (var i = 0; i < 100; i = i + 1) s = s + \"ab\"; print s;";
+    assert_eq!(eval_with_options(code, options), "ab".repeat(100));
+}
@@ -0,0 +1,31 @@
+use std::fmt::{Display, Formatter};
+
+use crate::{ast::expressions::BinaryOperator, code_span::CodeSpan};
+
+/// Something suspicious that happened during evaluation, but did not prevent
+/// it from continuing.
+#[derive(Debug, PartialEq)]
+pub enum Warning {
+    /// An operation on integers produced a result beyond the range of
+    /// integers exactly representable as a number.
+    PrecisionLoss(CodeSpan, BinaryOperator, f64),
+}
+
+impl Warning {
+    pub fn location(&self) -> &CodeSpan {
+        match self {
+            Warning::PrecisionLoss(span, _, _) => span,
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let warning = match self {
+            Warning::PrecisionLoss(_, op, result) => {
+                format!("Result of '{}' ({}) is too large to be exact", op, result)
+            }
+        };
+        write!(f, "{}: warning: {}", self.location(), warning)
+    }
+}
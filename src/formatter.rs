@@ -0,0 +1,329 @@
+use std::{iter::Peekable, vec::IntoIter};
+
+use crate::{
+    ast::{
+        declarations::{ClassDeclaration, FunctionDeclaration, VariableDeclaration},
+        expressions::Expression,
+        statements::{
+            Assert, Conditional, ForIn, ForLoop, Print, Statement, StatementVisitor, Statements,
+            WhileLoop,
+        },
+        types::Function,
+        LiteralValue,
+    },
+    parsing::{self, ParsingError},
+    scanning::{Token, TokenStream, TokenType},
+};
+
+/// Formats a program, one statement per line and blocks indented by four
+/// spaces. Comments are kept: each one is written before the statement it
+/// precedes, or after the statement ending on its line. Comments inside
+/// expressions move before their statement.
+pub fn format(code: &str) -> Result<String, ParsingError> {
+    let mut tokens = TokenStream::new(code).with_comments();
+    let stmts = parsing::parse(&mut tokens)?;
+    let mut formatter = Formatter {
+        comments: tokens.take_comments().into_iter().peekable(),
+        out: String::new(),
+        indent: 0,
+    };
+    for stmt in &stmts.stmts {
+        formatter.visit_statement(stmt);
+    }
+    formatter.comments_before(usize::MAX);
+    Ok(formatter.out)
+}
+
+struct Formatter {
+    comments: Peekable<IntoIter<Token>>,
+    out: String,
+    indent: usize,
+}
+
+impl Formatter {
+    fn write_indent(&mut self) {
+        self.out.push_str(&"    ".repeat(self.indent));
+    }
+
+    /// Writes the comments starting before `line` on their own lines.
+    fn comments_before(&mut self, line: usize) {
+        while let Some(comment) = self
+            .comments
+            .next_if(|comment| comment.get_span().start.line < line)
+        {
+            self.write_indent();
+            self.write_comment(&comment);
+            self.out.push('\n');
+        }
+    }
+
+    /// Writes the comment starting on `line`, if any, at the end of the
+    /// current line.
+    fn comment_on(&mut self, line: usize) {
+        if let Some(comment) = self
+            .comments
+            .next_if(|comment| comment.get_span().start.line == line)
+        {
+            self.out.push(' ');
+            self.write_comment(&comment);
+        }
+    }
+
+    fn write_comment(&mut self, comment: &Token) {
+        if let TokenType::Comment(text) = comment.get_type() {
+            self.out.push_str("//");
+            self.out.push_str(text);
+        }
+    }
+
+    /// Writes a block, from its opening brace to its closing brace.
+    fn write_block(&mut self, stmts: &Statements) {
+        self.out.push_str("{\n");
+        self.indent += 1;
+        for stmt in &stmts.stmts {
+            self.visit_statement(stmt);
+        }
+        if let Some(closing_brace) = stmts.closing_brace {
+            self.comments_before(closing_brace.start.line);
+        }
+        self.indent -= 1;
+        self.write_indent();
+        self.out.push('}');
+    }
+
+    /// Writes the statement controlled by a conditional or a loop, on the
+    /// same line as its header.
+    fn write_body(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Block(stmts) if stmt.as_for_loop().is_none() => self.write_block(stmts),
+            stmt => self.write_statement(stmt),
+        }
+    }
+
+    /// Writes a for loop with its header as written, instead of the block and
+    /// while loop it is desugared into.
+    fn write_for_loop(&mut self, for_loop: ForLoop) {
+        self.out.push_str(&for_loop.header());
+        self.out.push(' ');
+        self.write_body(&for_loop.while_loop.statement);
+        if let Some(else_statement) = &for_loop.while_loop.else_statement {
+            self.out.push_str(" else ");
+            self.write_body(else_statement);
+        }
+    }
+
+    /// Writes the parameters and body of a function.
+    fn write_function(&mut self, function: &Function) {
+        let args = function
+            .args
+            .iter()
+            .map(|arg| arg.ident.as_str())
+            .collect::<Vec<_>>();
+        self.out.push_str(&format!("({}) ", args.join(", ")));
+        self.write_block(&function.body);
+    }
+
+    /// Writes a statement without indentation or trailing newline.
+    fn write_statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Print(print) => self.visit_print(print),
+            Statement::Assert(assert) => self.visit_assert(assert),
+            Statement::VariableDeclaration(decl) => self.visit_variable_declaration(decl),
+            Statement::ClassDeclaration(decl) => self.visit_class_declaration(decl),
+            Statement::Block(stmts) => match stmt.as_for_loop() {
+                Some(for_loop) => self.write_for_loop(for_loop),
+                None => self.write_block(stmts),
+            },
+            Statement::Conditional(c) => self.visit_conditional(c),
+            Statement::WhileLoop(w) => self.visit_while_loop(w),
+            Statement::ForIn(l) => self.visit_for_in(l),
+            Statement::FunctionDeclaration(f) => self.visit_function_declaration(f),
+            Statement::Return(expr) => self.visit_return(expr),
+            Statement::Expression(_) | Statement::Break(_) | Statement::Continue(_) => {
+                self.out.push_str(&stmt.to_string())
+            }
+        }
+    }
+}
+
+impl StatementVisitor for Formatter {
+    type Return = ();
+
+    fn visit_statement(&mut self, stmt: &Statement) {
+        let location = stmt.get_location();
+        if !location.is_synthetic() {
+            self.comments_before(location.start.line);
+        }
+        self.write_indent();
+        self.write_statement(stmt);
+        if !location.is_synthetic() {
+            self.comment_on(location.end.line);
+        }
+        self.out.push('\n');
+    }
+
+    fn visit_print(&mut self, print: &Print) {
+        self.out.push_str(&format!("print {};", print.expr));
+    }
+
+    fn visit_assert(&mut self, assert: &Assert) {
+        self.out.push_str(&format!("assert {};", assert.condition));
+    }
+
+    fn visit_variable_declaration(&mut self, decl: &VariableDeclaration) {
+        self.out.push_str(&decl.to_string());
+    }
+
+    fn visit_class_declaration(&mut self, decl: &ClassDeclaration) {
+        self.out.push_str(&format!("class {}", decl.name));
+        if let Some(superclass) = &decl.superclass {
+            self.out.push_str(&format!(" < {}", superclass));
+        }
+        self.out.push_str(" {\n");
+        self.indent += 1;
+        for method in &decl.methods {
+            self.comments_before(method.name.location.start.line);
+            self.write_indent();
+            self.out.push_str(&method.name.ident);
+            self.write_function(&method.function);
+            self.out.push('\n');
+        }
+        self.indent -= 1;
+        self.write_indent();
+        self.out.push('}');
+    }
+
+    fn visit_conditional(&mut self, cond: &Conditional) {
+        self.out.push_str(&format!("if ({}) ", cond.condition));
+        self.write_body(&cond.then_statement);
+        if let Some(else_statement) = &cond.else_statement {
+            self.out.push_str(" else ");
+            self.write_body(else_statement);
+        }
+    }
+
+    fn visit_while_loop(&mut self, while_loop: &WhileLoop) {
+        if while_loop.for_keyword.is_some() || while_loop.increment.is_some() {
+            return self.write_for_loop(ForLoop {
+                initializer: None,
+                while_loop,
+            });
+        }
+        self.out
+            .push_str(&format!("while ({}) ", while_loop.condition));
+        self.write_body(&while_loop.statement);
+        if let Some(else_statement) = &while_loop.else_statement {
+            self.out.push_str(" else ");
+            self.write_body(else_statement);
+        }
+    }
+
+    fn visit_for_in(&mut self, for_in: &ForIn) {
+        self.out.push_str(&format!(
+            "for (var {} in {}) ",
+            for_in.variable, for_in.iterable
+        ));
+        self.write_body(&for_in.statement);
+        if let Some(else_statement) = &for_in.else_statement {
+            self.out.push_str(" else ");
+            self.write_body(else_statement);
+        }
+    }
+
+    fn visit_function_declaration(&mut self, fd: &FunctionDeclaration) {
+        self.out.push_str(&format!("fun {}", fd.name));
+        self.write_function(&fd.function);
+    }
+
+    fn visit_return(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Literal(l) if l.value == LiteralValue::Nil => self.out.push_str("return;"),
+            _ => self.out.push_str(&format!("return {};", expr)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_comments() {
+        let code = "// Greets someone.\n\
+            fun greet(name) {\n\
+            // The greeting.\n\
+            print \"Hello, \" + name; // inline\n\
+            }\n\
+            greet(\"you\"); // call\n\
+            // end";
+        let expected = "// Greets someone.\n\
+            fun greet(name) {\n    \
+                // The greeting.\n    \
+                print \"Hello, \" + name; // inline\n\
+            }\n\
+            greet(\"you\"); // call\n\
+            // end\n";
+        assert_eq!(format(code).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn formats_statements() {
+        let code = "class A{m(a,b){return a;}} class B<A{} if(x)print 1;else{print 2;}\
+            while(true){break;} for (var k in o) continue;";
+        let expected = "class A {\n    \
+                m(a, b) {\n        \
+                    return a;\n    \
+                }\n\
+            }\n\
+            class B < A {\n\
+            }\n\
+            if (x) print 1; else {\n    \
+                print 2;\n\
+            }\n\
+            while (true) {\n    \
+                break;\n\
+            }\n\
+            for (var k in o) continue;\n";
+        assert_eq!(format(code).unwrap(), expected);
+    }
+
+    #[test]
+    fn keeps_for_loops() {
+        let code = "for (var i = 0; i < n; i = i + 1) print i;\n\
+            for (;;) {\n    \
+                break;\n\
+            }\n\
+            for (i = 0; i < n;) i = i + 1;\n\
+            if (x) for (var j = 0;;) break;\n\
+            {\n    \
+                var k = 0;\n    \
+                while (true) break;\n\
+            }\n";
+        assert_eq!(format(code).unwrap(), code);
+    }
+
+    #[test]
+    fn keeps_comments_at_the_end_of_blocks() {
+        let code = "fun f() {\n    \
+                print 1;\n    \
+                // end of f\n\
+            }\n\
+            if (x) {\n    \
+                // nothing yet\n\
+            }\n\
+            // after\n";
+        assert_eq!(format(code).unwrap(), code);
+    }
+
+    #[test]
+    fn comments_without_code() {
+        assert_eq!(format("").unwrap(), "");
+        assert_eq!(format("// a\n\n//b").unwrap(), "// a\n//b\n");
+    }
+
+    #[test]
+    fn parsing_errors() {
+        assert!(format("print // missing\n").is_err());
+    }
+}
@@ -0,0 +1,9 @@
+pub mod ast;
+pub mod code_span;
+pub mod error;
+pub mod eval;
+pub mod formatter;
+pub mod location;
+pub mod location_tracking_iterator;
+pub mod parsing;
+pub mod scanning;
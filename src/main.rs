@@ -1,79 +1,707 @@
 use std::{
     env,
-    io::{Read, Write},
+    fmt::{Display, Formatter},
+    io::{BufRead, Write},
+    panic::AssertUnwindSafe,
 };
 
-use crate::{
-    ast::statements::StatementVisitor,
-    eval::{output_stream::OutputStream, prelude},
+use rlox::{
+    ast::{
+        statements::{StatementVisitor, Statements},
+        types::ValueType,
+    },
+    eval::{output_stream::OutputStream, prelude, Evaluator, Resolver, RuntimeError},
+    formatter,
+    parsing::{self, ParsingError},
     scanning::TokenStream,
 };
 
-mod ast;
-mod code_span;
-mod error;
-mod eval;
-mod location;
-mod location_tracking_iterator;
-mod parsing;
-mod scanning;
+/// Errors that prevented a piece of code from running successfully.
+#[derive(Debug)]
+enum RunError {
+    /// The code could not be parsed, so nothing was evaluated.
+    Parsing(ParsingError),
+    /// The resolver rejected the code, so nothing was evaluated.
+    Resolving(Vec<ParsingError>),
+    /// Every runtime error raised by the statements of the code, in order.
+    Runtime(Vec<RuntimeError>),
+    /// The interpreter itself failed, with the given message.
+    Internal(String),
+}
+
+/// A way to parse the code to run, which differs between files and the prompt.
+type Parse = fn(&mut TokenStream) -> Result<Statements, ParsingError>;
+
+/// The command line arguments of the interpreter.
+#[derive(Debug, Default, PartialEq)]
+struct Config {
+    /// Only check the files instead of running them.
+    check: bool,
+    /// Print the files formatted instead of running them.
+    format: bool,
+    /// The maximum number of iterations of a loop.
+    max_iterations: Option<usize>,
+    /// The maximum depth of nested calls.
+    max_depth: Option<usize>,
+    /// The files to run, or none to prompt for code.
+    files: Vec<String>,
+}
+
+impl Config {
+    /// Parses the arguments following the program name. Options come before
+    /// the files. Returns `None` if the arguments are invalid.
+    fn parse(args: &[String]) -> Option<Config> {
+        let mut config = Config::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--check" => config.check = true,
+                "--fmt" => config.format = true,
+                "--max-iterations" => config.max_iterations = Some(args.next()?.parse().ok()?),
+                "--max-depth" => config.max_depth = Some(args.next()?.parse().ok()?),
+                _ if arg.starts_with("--") => return None,
+                _ => {
+                    config.files.push(arg.clone());
+                    config.files.extend(args.cloned());
+                    break;
+                }
+            }
+        }
+        if (config.check || config.format) && config.files.is_empty() {
+            return None;
+        }
+        Some(config)
+    }
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let res = match args.len() {
-        0 => print_usage(),
-        1 => run_prompt(),
-        2 => run_file(&args[1]),
-        _ => print_usage(),
+    let res = match Config::parse(args.get(1..).unwrap_or_default()) {
+        None => print_usage(),
+        Some(config) if config.check => check_files(&config.files, &mut std::io::stdout()),
+        Some(config) if config.format => format_files(&config.files, &mut std::io::stdout()),
+        Some(config) if config.files.is_empty() => run_prompt(&config),
+        Some(config) => run_files(&config),
     };
-    std::process::exit(res.unwrap_or(64) as i32)
+    let code = res.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        74
+    });
+    std::process::exit(code as i32)
 }
 
 fn print_usage() -> std::io::Result<u8> {
-    eprintln!("Usage: rlox FILE");
+    eprintln!("Usage: rlox [--check | --fmt] [--max-iterations N] [--max-depth N] [FILE...]");
     Ok(64)
 }
 
 /// Prompts the user to write code and processes it.
-fn run_prompt() -> std::io::Result<u8> {
+fn run_prompt(config: &Config) -> std::io::Result<u8> {
+    let mut evaluator = new_evaluator(OutputStream::StdOut(std::io::stdout()), config);
+    prompt(
+        &mut std::io::stdin().lock(),
+        &mut std::io::stdout(),
+        &mut evaluator,
+    )
+}
+
+/// Reads code from the input line by line and runs it, until the end of the
+/// input is reached. Lines are accumulated until they form complete
+/// statements, so that code can span multiple lines. Prompts and errors are
+/// written to the output, while the program itself prints to the evaluator's
+/// output stream. Lines starting with `:` are meta-commands instead of code.
+fn prompt(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    evaluator: &mut Evaluator,
+) -> std::io::Result<u8> {
+    let mut code = String::new();
     loop {
-        print!("> ");
-        std::io::stdout().flush()?;
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input)?;
-        if input.is_empty() {
+        write!(output, "{}", if code.is_empty() { "> " } else { "... " })?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
             return Ok(0);
         }
-        run(&mut input, OutputStream::StdOut(std::io::stdout()));
+        if code.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        if code.is_empty() && line.starts_with(':') {
+            meta_command(line.trim(), output, evaluator)?;
+            continue;
+        }
+        code.push_str(&line);
+        match run(&code, parsing::parse_input, evaluator) {
+            Err(RunError::Parsing(ParsingError::Incomplete(_))) => continue,
+            Err(e) => writeln!(output, "{}", e)?,
+            Ok(()) => (),
+        }
+        code.clear();
     }
 }
 
-/// Runs a whole file.
-fn run_file(file_name: &str) -> std::io::Result<u8> {
-    let mut file = std::fs::File::open(file_name)?;
-    let mut code = String::new();
-    file.read_to_string(&mut code)?;
-    run(&mut code, OutputStream::StdOut(std::io::stdout()));
-    Ok(0)
+/// Runs a meta-command of the prompt. `:vars` lists the global variables
+/// defined so far, leaving out the native functions of the prelude.
+fn meta_command(
+    command: &str,
+    output: &mut impl Write,
+    evaluator: &mut Evaluator,
+) -> std::io::Result<()> {
+    match command {
+        ":vars" => {
+            for (name, value) in evaluator.globals() {
+                if !matches!(value, ValueType::NativeFunction(_)) {
+                    writeln!(output, "{} = {}", name, value)?;
+                }
+            }
+            Ok(())
+        }
+        _ => writeln!(output, "unknown command: {}", command),
+    }
 }
 
-/// Runs a single line of code.
-fn run(code: &mut str, out: OutputStream) -> Option<u8> {
-    let mut tokens = TokenStream::new(code);
-    let tree = parsing::parse(&mut tokens);
-    let mut evaluator = eval::Evaluator::new(out);
-    evaluator.register_prelude(prelude());
-    match tree {
-        Err(e) => print!("{}", e),
-        Ok(stmts) => {
-            for stmt in &stmts.stmts {
-                let res = evaluator.visit_statement(stmt);
-                match res {
-                    Ok(_) => (),
-                    Err(e) => print!("{}", e),
+/// Runs whole files in order.
+fn run_files(config: &Config) -> std::io::Result<u8> {
+    let mut evaluator = new_evaluator(OutputStream::StdOut(std::io::stdout()), config);
+    run_files_with(&config.files, &mut std::io::stdout(), &mut evaluator)
+}
+
+/// Runs files in order with a single evaluator, so that the globals defined
+/// by a file are visible to the following ones. A file that fails does not
+/// prevent the next ones from running; its errors are written to the output
+/// prefixed with its name, and the exit code is that of the first failure. A
+/// file that cannot be read fails with exit code 66.
+fn run_files_with(
+    file_names: &[String],
+    output: &mut impl Write,
+    evaluator: &mut Evaluator,
+) -> std::io::Result<u8> {
+    let mut exit_code = 0;
+    for file_name in file_names {
+        let code = match std::fs::read_to_string(file_name) {
+            Ok(code) => code,
+            Err(e) => {
+                writeln!(output, "{}: {}", file_name, e)?;
+                if exit_code == 0 {
+                    exit_code = 66;
+                }
+                continue;
+            }
+        };
+        if let Err(e) = run(&code, parsing::parse, evaluator) {
+            for line in e.to_string().lines() {
+                writeln!(output, "{}: {}", file_name, line)?;
+            }
+            if exit_code == 0 {
+                exit_code = e.exit_code();
+            }
+        }
+    }
+    Ok(exit_code)
+}
+
+/// Parses and resolves files without running them, writing every error found
+/// to the output prefixed with the file name. Each file is checked on its own,
+/// with only the prelude as predefined globals. The exit code is that of the
+/// first failure, 66 if the file cannot be read.
+fn check_files(file_names: &[String], output: &mut impl Write) -> std::io::Result<u8> {
+    let mut exit_code = 0;
+    for file_name in file_names {
+        let code = match std::fs::read_to_string(file_name) {
+            Ok(code) => code,
+            Err(e) => {
+                writeln!(output, "{}: {}", file_name, e)?;
+                if exit_code == 0 {
+                    exit_code = 66;
                 }
+                continue;
+            }
+        };
+        if let Err(errors) = check(&code) {
+            for e in errors {
+                writeln!(output, "{}: {}", file_name, e)?;
+            }
+            if exit_code == 0 {
+                exit_code = 65;
+            }
+        }
+    }
+    Ok(exit_code)
+}
+
+/// Writes the files formatted to the output, keeping their comments. A file
+/// that cannot be read or parsed is skipped, with its error written prefixed
+/// with its name instead.
+fn format_files(file_names: &[String], output: &mut impl Write) -> std::io::Result<u8> {
+    let mut exit_code = 0;
+    for file_name in file_names {
+        let code = match std::fs::read_to_string(file_name) {
+            Ok(code) => code,
+            Err(e) => {
+                writeln!(output, "{}: {}", file_name, e)?;
+                if exit_code == 0 {
+                    exit_code = 66;
+                }
+                continue;
+            }
+        };
+        match formatter::format(&code) {
+            Ok(formatted) => write!(output, "{}", formatted)?,
+            Err(e) => {
+                writeln!(output, "{}: {}", file_name, e)?;
+                if exit_code == 0 {
+                    exit_code = 65;
+                }
+            }
+        }
+    }
+    Ok(exit_code)
+}
+
+/// Parses a piece of code and runs the resolver on it.
+fn check(code: &str) -> Result<(), Vec<ParsingError>> {
+    let stmts = parsing::parse(&mut TokenStream::new(code)).map_err(|e| vec![e])?;
+    let mut resolver = Resolver::new().check_unbound_names(true);
+    for (name, _, _) in prelude() {
+        resolver.declare_global(name);
+    }
+    resolver.resolve(&stmts)
+}
+
+/// Creates an evaluator with the prelude registered and the limits of the
+/// configuration.
+fn new_evaluator(out: OutputStream, config: &Config) -> Evaluator {
+    let mut evaluator = Evaluator::new(out);
+    evaluator.register_prelude(prelude());
+    let options = evaluator.options_mut();
+    options.max_iterations = config.max_iterations;
+    options.max_call_depth = config.max_depth;
+    evaluator
+}
+
+/// Runs a piece of code, parsed with `parse`. A runtime error does not stop
+/// the evaluation of the following statements, so every runtime error is
+/// returned. If the interpreter panics, the panic is reported as an internal
+/// error instead of aborting.
+fn run(code: &str, parse: Parse, evaluator: &mut Evaluator) -> Result<(), RunError> {
+    std::panic::catch_unwind(AssertUnwindSafe(|| run_unguarded(code, parse, evaluator)))
+        .unwrap_or_else(|payload| {
+            let message = if let Some(s) = payload.downcast_ref::<&str>() {
+                s.to_string()
+            } else if let Some(s) = payload.downcast_ref::<String>() {
+                s.clone()
+            } else {
+                "unknown error".to_string()
+            };
+            Err(RunError::Internal(message))
+        })
+}
+
+fn run_unguarded(code: &str, parse: Parse, evaluator: &mut Evaluator) -> Result<(), RunError> {
+    let mut tokens = TokenStream::new(code);
+    let stmts = parse(&mut tokens).map_err(RunError::Parsing)?;
+    Resolver::new()
+        .resolve(&stmts)
+        .map_err(RunError::Resolving)?;
+    let errors = stmts
+        .stmts
+        .iter()
+        .filter_map(|stmt| evaluator.visit_statement(stmt).err())
+        .collect::<Vec<_>>();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(RunError::Runtime(errors))
+    }
+}
+
+impl RunError {
+    /// The exit code of the process when running a file fails.
+    fn exit_code(&self) -> u8 {
+        match self {
+            RunError::Parsing(_) | RunError::Resolving(_) => 65,
+            RunError::Runtime(_) | RunError::Internal(_) => 70,
+        }
+    }
+}
+
+impl Display for RunError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunError::Parsing(e) => write!(f, "{}", e),
+            RunError::Resolving(errors) => write_lines(f, errors),
+            RunError::Runtime(errors) => write_lines(f, errors),
+            RunError::Internal(message) => write!(f, "internal error: {}", message),
+        }
+    }
+}
+
+/// Writes errors one per line, without a trailing newline.
+fn write_lines(f: &mut Formatter<'_>, errors: &[impl Display]) -> std::fmt::Result {
+    let mut iter = errors.iter();
+    if let Some(first) = iter.next() {
+        write!(f, "{}", first)?;
+    }
+    for e in iter {
+        write!(f, "\n{}", e)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rlox::code_span::CodeSpan;
+
+    use super::*;
+
+    fn run_captured(code: &str) -> Result<(), RunError> {
+        run(
+            code,
+            parsing::parse,
+            &mut new_evaluator(OutputStream::Buffer(Default::default()), &Config::default()),
+        )
+    }
+
+    #[test]
+    fn run_collects_runtime_errors() {
+        let code = "print 1; print a; print 2; print 1 - nil;";
+        match run_captured(code) {
+            Err(RunError::Runtime(errors)) => {
+                assert_eq!(errors.len(), 2);
+                assert!(matches!(&errors[0], RuntimeError::UnboundName(_, name) if name == "a"));
+                assert!(matches!(errors[1], RuntimeError::InvalidOperand(..)));
             }
+            res => panic!("expected runtime errors, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn run_reports_parsing_errors() {
+        assert!(matches!(run_captured("print 1"), Err(RunError::Parsing(_))));
+    }
+
+    #[test]
+    fn run_reports_resolving_errors() {
+        match run_captured("return 1; { var a = a; }") {
+            Err(RunError::Resolving(errors)) => assert_eq!(errors.len(), 2),
+            res => panic!("expected resolving errors, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn run_without_errors() {
+        assert!(run_captured("print 1;").is_ok());
+    }
+
+    #[test]
+    fn run_empty_programs() {
+        for code in ["", " \n\t\n", "// only a comment", "// one\n  // two\n"] {
+            let (mut evaluator, printed) = Evaluator::capturing();
+            assert!(
+                run(code, parsing::parse, &mut evaluator).is_ok(),
+                "{:?}",
+                code
+            );
+            assert!(printed.borrow().is_empty());
+        }
+    }
+
+    #[test]
+    fn run_reports_panics() {
+        fn boom(_: Vec<ValueType>, _: CodeSpan) -> rlox::eval::Result<ValueType> {
+            panic!("boom")
+        }
+        let (mut evaluator, _) = Evaluator::capturing();
+        evaluator.register_prelude(vec![("boom", boom, 0)]);
+        match run("print 1; boom();", parsing::parse, &mut evaluator) {
+            Err(RunError::Internal(message)) => assert_eq!(message, "boom"),
+            res => panic!("expected an internal error, got {:?}", res),
+        }
+        assert!(run("print 2;", parsing::parse, &mut evaluator).is_ok());
+    }
+
+    /// A directory of files for one test, removed with its files when dropped.
+    /// Tests run in parallel, so each one names its own directory.
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new(test: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("rlox-{}-{}", std::process::id(), test));
+            std::fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        /// Returns the path of a file of the directory that does not exist.
+        fn missing(&self, name: &str) -> String {
+            self.0.join(name).to_string_lossy().into_owned()
+        }
+
+        fn file(&self, name: &str, code: &str) -> String {
+            let path = self.missing(name);
+            std::fs::write(&path, code).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
         }
     }
-    None
+
+    #[test]
+    fn run_files_share_globals() {
+        let dir = TempDir::new("run_files_share_globals");
+        let files = [
+            dir.file("helpers.lox", "fun double(x) { return 2 * x; }"),
+            dir.file("main.lox", "print double(21);"),
+        ];
+        let (mut evaluator, printed) = Evaluator::capturing();
+        let mut output = Vec::new();
+        assert_eq!(
+            run_files_with(&files, &mut output, &mut evaluator).unwrap(),
+            0
+        );
+        assert_eq!(*printed.borrow(), "42");
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn run_files_report_file_names() {
+        let dir = TempDir::new("run_files_report_file_names");
+        let files = [
+            dir.file("unbound.lox", "print a;"),
+            dir.file("fine.lox", "print 1;"),
+            dir.file("unparsable.lox", "print"),
+        ];
+        let (mut evaluator, printed) = Evaluator::capturing();
+        let mut output = Vec::new();
+        assert_eq!(
+            run_files_with(&files, &mut output, &mut evaluator).unwrap(),
+            70
+        );
+        assert_eq!(*printed.borrow(), "1");
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(&format!("{}: ", files[0])));
+        assert!(lines[1].starts_with(&format!("{}: ", files[2])));
+    }
+
+    #[test]
+    fn run_files_after_missing_file() {
+        let dir = TempDir::new("run_files_after_missing_file");
+        let missing = dir.missing("missing.lox");
+        let files = [missing.clone(), dir.file("after.lox", "print 1;")];
+        let (mut evaluator, printed) = Evaluator::capturing();
+        let mut output = Vec::new();
+        assert_eq!(
+            run_files_with(&files, &mut output, &mut evaluator).unwrap(),
+            66
+        );
+        assert_eq!(*printed.borrow(), "1");
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.starts_with(&format!("{}: ", missing)));
+    }
+
+    #[test]
+    fn run_files_report_missing_semicolons() {
+        let dir = TempDir::new("run_files_report_missing_semicolons");
+        let files = [dir.file("no_semicolon.lox", "print 1")];
+        let (mut evaluator, _) = Evaluator::capturing();
+        let mut output = Vec::new();
+        assert_eq!(
+            run_files_with(&files, &mut output, &mut evaluator).unwrap(),
+            65
+        );
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("{}: expected ';' at [1,7]\n", files[0])
+        );
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_config() {
+        assert_eq!(Config::parse(&[]), Some(Config::default()));
+        assert_eq!(
+            Config::parse(&args(&[
+                "--max-iterations",
+                "100",
+                "--max-depth",
+                "8",
+                "a.lox",
+                "--check"
+            ])),
+            Some(Config {
+                max_iterations: Some(100),
+                max_depth: Some(8),
+                files: args(&["a.lox", "--check"]),
+                ..Config::default()
+            })
+        );
+        for invalid in [
+            &["--check"][..],
+            &["--max-iterations"],
+            &["--max-depth", "-1", "a.lox"],
+            &["--unknown", "a.lox"],
+        ] {
+            assert_eq!(Config::parse(&args(invalid)), None, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn run_files_with_limits() {
+        let dir = TempDir::new("run_files_with_limits");
+        let files = [
+            dir.file("loop.lox", "var i = 0; while (i < 1000) i = i + 1;"),
+            dir.file("recursion.lox", "fun f() { f(); } f();"),
+        ];
+        let config =
+            Config::parse(&args(&["--max-iterations", "100", "--max-depth", "10"])).unwrap();
+        let mut evaluator = new_evaluator(OutputStream::Buffer(Default::default()), &config);
+        let mut output = Vec::new();
+        assert_eq!(
+            run_files_with(&files, &mut output, &mut evaluator).unwrap(),
+            70
+        );
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("maximum of 100 iterations"), "{}", output);
+        assert!(output.contains("maximum depth of 10"), "{}", output);
+    }
+
+    #[test]
+    fn check_files_without_running() {
+        let dir = TempDir::new("check_files_without_running");
+        let files = [
+            dir.file("checked.lox", "print clock();\nprint a;\nprint b;"),
+            dir.file("clean.lox", "var a = 1; print a;"),
+        ];
+        let mut output = Vec::new();
+        assert_eq!(check_files(&files, &mut output).unwrap(), 65);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            format!(
+                "{0}: undeclared name 'a' at [2,6]-[2,7]\n\
+                {0}: undeclared name 'b' at [3,6]-[3,7]\n",
+                files[0]
+            )
+        );
+
+        let mut output = Vec::new();
+        assert_eq!(check_files(&files[1..], &mut output).unwrap(), 0);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn check_files_after_missing_file() {
+        let dir = TempDir::new("check_files_after_missing_file");
+        let missing = dir.missing("missing.lox");
+        let files = [
+            dir.file("a.lox", "var a = 1;"),
+            missing.clone(),
+            dir.file("b.lox", "print b;"),
+        ];
+        let mut output = Vec::new();
+        assert_eq!(check_files(&files, &mut output).unwrap(), 66);
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(&format!("{}: ", missing)));
+        assert_eq!(
+            lines[1],
+            format!("{}: undeclared name 'b' at [1,6]-[1,7]", files[2])
+        );
+    }
+
+    #[test]
+    fn format_files_keeps_comments() {
+        let dir = TempDir::new("format_files_keeps_comments");
+        let files = [
+            dir.file("formatted.lox", "// Say hi.\nprint   \"hi\";// twice\n"),
+            dir.file("broken.lox", "print"),
+        ];
+        let mut output = Vec::new();
+        assert_eq!(format_files(&files, &mut output).unwrap(), 65);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            format!(
+                "// Say hi.\nprint \"hi\"; // twice\n{}: incomplete input at [1,5]\n",
+                files[1]
+            )
+        );
+    }
+
+    #[test]
+    fn format_files_after_missing_file() {
+        let dir = TempDir::new("format_files_after_missing_file");
+        let missing = dir.missing("missing.lox");
+        let files = [missing.clone(), dir.file("print.lox", "print 1;")];
+        let mut output = Vec::new();
+        assert_eq!(format_files(&files, &mut output).unwrap(), 66);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with(&format!("{}: ", missing)));
+        assert!(output.ends_with("\nprint 1;\n"));
+    }
+
+    #[test]
+    fn prompt_until_eof() {
+        let (mut evaluator, printed) = Evaluator::capturing();
+        let mut input = "var a = 1;\n   \t\nprint a;\n".as_bytes();
+        let mut output = Vec::new();
+        assert_eq!(prompt(&mut input, &mut output, &mut evaluator).unwrap(), 0);
+        assert_eq!(String::from_utf8(output).unwrap(), "> > > > ");
+        assert_eq!(*printed.borrow(), "1");
+    }
+
+    #[test]
+    fn prompt_multiline() {
+        let (mut evaluator, printed) = Evaluator::capturing();
+        let mut input = "fun f(a) {\n  print a;\n}\nf(3);\n".as_bytes();
+        let mut output = Vec::new();
+        assert_eq!(prompt(&mut input, &mut output, &mut evaluator).unwrap(), 0);
+        assert_eq!(String::from_utf8(output).unwrap(), "> ... ... > > ");
+        assert_eq!(*printed.borrow(), "3");
+    }
+
+    #[test]
+    fn prompt_waits_for_semicolon() {
+        let (mut evaluator, printed) = Evaluator::capturing();
+        let mut input = "print 1\n;\n".as_bytes();
+        let mut output = Vec::new();
+        assert_eq!(prompt(&mut input, &mut output, &mut evaluator).unwrap(), 0);
+        assert_eq!(String::from_utf8(output).unwrap(), "> ... > ");
+        assert_eq!(*printed.borrow(), "1");
+    }
+
+    #[test]
+    fn prompt_reports_errors() {
+        let (mut evaluator, _) = Evaluator::capturing();
+        let mut input = "print a;".as_bytes();
+        let mut output = Vec::new();
+        assert_eq!(prompt(&mut input, &mut output, &mut evaluator).unwrap(), 0);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("> "));
+        assert!(output.contains("Unbound name a"));
+        assert!(output.ends_with("> "));
+    }
+
+    #[test]
+    fn prompt_lists_variables() {
+        let (mut evaluator, _) = Evaluator::capturing();
+        evaluator.register_prelude(prelude());
+        let mut input = "var x = 1;\nvar s = \"a\";\n:vars\n:what\n".as_bytes();
+        let mut output = Vec::new();
+        assert_eq!(prompt(&mut input, &mut output, &mut evaluator).unwrap(), 0);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "> > > s = a\nx = 1\n> unknown command: :what\n> "
+        );
+    }
 }
@@ -2,14 +2,12 @@ use super::Result;
 use crate::{
     ast::{
         declarations::{ClassDeclaration, FunctionDeclaration, VariableDeclaration},
-        expressions::{Expression, Identifier, Literal},
+        expressions::Identifier,
         statements::{Statement, Statements},
         types::Function,
-        LiteralValue,
     },
-    code_span::CodeSpan,
     parsing::{
-        consume, parse_expression,
+        consume, consume_semicolon, nested, parse_expression,
         statements::{parse_declarations, parse_statement},
         ParsingError,
     },
@@ -21,15 +19,15 @@ pub fn parse_declaration(tokens: &mut TokenStream) -> Result<Statement> {
         match t.get_type() {
             TokenType::Var => {
                 let var_dec = parse_variable_declaration(tokens)?;
-                consume(tokens, TokenType::Semicolon)?;
+                consume_semicolon(tokens)?;
                 Ok(Statement::VariableDeclaration(var_dec))
             }
             TokenType::Fun => {
-                let fun_dec = parse_function_declaration(tokens)?;
+                let fun_dec = nested(tokens, parse_function_declaration)?;
                 Ok(Statement::FunctionDeclaration(fun_dec))
             }
             TokenType::Class => {
-                let class_dec = parse_class_declaration(tokens)?;
+                let class_dec = nested(tokens, parse_class_declaration)?;
                 Ok(Statement::ClassDeclaration(class_dec))
             }
             _ => parse_statement(tokens),
@@ -48,6 +46,22 @@ pub fn parse_class_declaration(tokens: &mut TokenStream) -> Result<ClassDeclarat
     let mut methods = Vec::new();
     match token.consume() {
         TokenType::Identifier(name) => {
+            let superclass = if consume(tokens, TokenType::Less).is_ok() {
+                let token = tokens.force_next()?;
+                let span = token.get_span();
+                match token.consume() {
+                    TokenType::Identifier(ident) => Some(Identifier {
+                        ident,
+                        location: span,
+                        binding: Default::default(),
+                    }),
+                    token_type => {
+                        return Err(ParsingError::UnexpectedToken(Token::new(token_type, span)))
+                    }
+                }
+            } else {
+                None
+            };
             consume(tokens, TokenType::LeftBrace)?;
             while tokens.peek().is_some_and(|t| t.is_identifier()) {
                 methods.push(parse_function(tokens)?);
@@ -57,7 +71,9 @@ pub fn parse_class_declaration(tokens: &mut TokenStream) -> Result<ClassDeclarat
                 name: Identifier {
                     ident: name,
                     location: span,
+                    binding: Default::default(),
                 },
+                superclass,
                 methods,
             })
         }
@@ -72,25 +88,20 @@ pub fn parse_variable_declaration(tokens: &mut TokenStream) -> Result<VariableDe
     match token.consume() {
         TokenType::Identifier(s) => {
             let initializer = if consume(tokens, TokenType::Equal).is_ok() {
-                parse_expression(tokens)?
+                Some(parse_expression(tokens)?)
             } else {
-                let location = tokens.current_position();
-                Expression::Literal(Literal::new(
-                    LiteralValue::Nil,
-                    CodeSpan::new(location, location),
-                ))
+                None
             };
             Ok(VariableDeclaration {
                 name: Identifier {
                     ident: s,
                     location: position,
+                    binding: Default::default(),
                 },
                 initializer,
             })
         }
-        token_type => Err(ParsingError::UnexpectedToken(Token::new(
-            token_type, position,
-        ))),
+        token_type => Err(invalid_name(Token::new(token_type, position))),
     }
 }
 
@@ -104,38 +115,49 @@ pub fn parse_function(tokens: &mut TokenStream) -> Result<FunctionDeclaration> {
     let span = token.get_span();
     if let TokenType::Identifier(s) = token.get_type() {
         consume(tokens, TokenType::LeftParen)?;
-        let params = parse_parameters(tokens);
+        let params = parse_parameters(tokens)?;
         consume(tokens, TokenType::RightParen)?;
         consume(tokens, TokenType::LeftBrace)?;
-        let stmts = parse_declarations(tokens);
-        consume(tokens, TokenType::RightBrace)?;
+        let stmts = parse_declarations(tokens)?;
+        let closing_brace = consume(tokens, TokenType::RightBrace)?;
 
         Ok(FunctionDeclaration {
             name: Identifier {
                 ident: s.clone(),
                 location: span,
+                binding: Default::default(),
             },
             function: Function {
                 args: params,
-                body: Statements { stmts },
+                body: Statements {
+                    stmts,
+                    closing_brace: Some(closing_brace.get_span()),
+                },
                 span,
             }
             .into(),
         })
     } else {
-        Err(ParsingError::UnexpectedToken(token))
+        Err(invalid_name(token))
     }
 }
 
-fn parse_parameters(tokens: &mut TokenStream) -> Vec<Identifier> {
+fn parse_parameters(tokens: &mut TokenStream) -> Result<Vec<Identifier>> {
     let mut params = Vec::<Identifier>::new();
     let mut save = tokens.save_position();
 
     while let Some(token) = tokens.next() {
         if let TokenType::Identifier(ident) = token.get_type() {
+            if params.iter().any(|param| param.ident == *ident) {
+                return Err(ParsingError::DuplicateParameter(
+                    token.get_span(),
+                    ident.clone(),
+                ));
+            }
             params.push(Identifier {
                 ident: ident.clone(),
                 location: token.get_span(),
+                binding: Default::default(),
             });
             save = tokens.save_position();
             if let Some(t) = tokens.peek() {
@@ -145,13 +167,24 @@ fn parse_parameters(tokens: &mut TokenStream) -> Vec<Identifier> {
                 }
             }
             break;
+        } else if token.get_type().is_keyword() {
+            return Err(ParsingError::KeywordAsIdentifier(token));
         } else {
             break;
         }
     }
 
     tokens.load_position(save);
-    params
+    Ok(params)
+}
+
+/// The error for a token that should have been the name of a declaration.
+fn invalid_name(token: Token) -> ParsingError {
+    if token.get_type().is_keyword() {
+        ParsingError::KeywordAsIdentifier(token)
+    } else {
+        ParsingError::UnexpectedToken(token)
+    }
 }
 
 #[cfg(test)]
@@ -163,7 +196,8 @@ mod tests {
         parse_variable_declaration,
         "var a = 1;",
         "var b;",
-        "var c = 1 + 1 / 2;"
+        "var c = 1 + 1 / 2;",
+        "var d = nil;"
     );
 
     gen_tests!(
@@ -174,11 +208,45 @@ mod tests {
         "fun g(a, b, c) { print a + b * c;\nprint \"hello\";\n }"
     );
 
+    #[test]
+    fn keyword_as_name() {
+        for code in ["var and = 1;", "fun if() {}", "fun f(a, while) {}"] {
+            assert!(matches!(
+                parse_declaration(&mut TokenStream::new(code)),
+                Err(ParsingError::KeywordAsIdentifier(_))
+            ));
+        }
+        assert!(matches!(
+            parse_declaration(&mut TokenStream::new("var 1 = 1;")),
+            Err(ParsingError::UnexpectedToken(_))
+        ));
+    }
+
+    #[test]
+    fn duplicate_parameter() {
+        for code in [
+            "fun f(a, a) {}",
+            "fun f(a, b, a) {}",
+            "class A { m(x, x) {} }",
+        ] {
+            assert!(
+                matches!(
+                    parse_declaration(&mut TokenStream::new(code)),
+                    Err(ParsingError::DuplicateParameter(_, name)) if name == "a" || name == "x"
+                ),
+                "{}",
+                code
+            );
+        }
+        assert!(parse_declaration(&mut TokenStream::new("fun f(a, b) {}")).is_ok());
+    }
+
     gen_tests!(
         test_class_declarations,
         parse_class_declaration,
         "class EmptyClass {\n}",
         "class OneMethod {\nempty_method() {  }\n}",
-        "class TwoMethods {\nmethod_one() { return 2;\n }\nmethod_two(a) { print a;\n }\n}"
+        "class TwoMethods {\nmethod_one() { return 2;\n }\nmethod_two(a) { print a;\n }\n}",
+        "class Sub < Base {\nm() { return super.m();\n }\n}"
     );
 }
@@ -1,73 +1,29 @@
-use std::convert::TryFrom;
+use std::{convert::TryFrom, rc::Rc};
 
 use crate::{
     ast::{
         expressions::{
             Assignment, Binary, BinaryOperator, Call, Expression, Get, Identifier, Literal, Set,
-            Unary, UnaryOperator,
+            Super, Unary, UnaryOperator,
         },
         LiteralValue::{False, Nil, NumberLiteral, StringLiteral, True},
     },
     code_span::CodeSpan,
-    parsing::{consume, try_parse, ParsingError, Result},
+    parsing::{consume, nested, ParsingError, Result},
     scanning::{Token, TokenStream, TokenType},
 };
 
 pub fn parse_expression(tokens: &mut TokenStream) -> Result<Expression> {
-    parse_assignment(tokens)
-}
-
-fn parse_logic_or(tokens: &mut TokenStream) -> Result<Expression> {
-    let mut expr = parse_logic_and(tokens)?;
-
-    while let Some(token) = tokens.peek() {
-        if token.is_of_type(TokenType::Or) {
-            tokens.next();
-            let right = parse_logic_and(tokens)?;
-            let span = CodeSpan::new(expr.get_location().start, right.get_location().end);
-            expr = Expression::BinaryOperation(Binary {
-                operator: BinaryOperator::Disjunction,
-                left: Box::new(expr),
-                right: Box::new(right),
-                location: span,
-            });
-        } else {
-            break;
-        }
-    }
-
-    Ok(expr)
-}
-
-fn parse_logic_and(tokens: &mut TokenStream) -> Result<Expression> {
-    let mut expr = parse_equality(tokens)?;
-
-    while let Some(token) = tokens.peek() {
-        if token.is_of_type(TokenType::And) {
-            tokens.next();
-            let right = parse_equality(tokens)?;
-            let span = CodeSpan::new(expr.get_location().start, right.get_location().end);
-            expr = Expression::BinaryOperation(Binary {
-                operator: BinaryOperator::Conjunction,
-                left: Box::new(expr),
-                right: Box::new(right),
-                location: span,
-            });
-        } else {
-            break;
-        }
-    }
-
-    Ok(expr)
+    nested(tokens, parse_assignment)
 }
 
 fn parse_assignment(tokens: &mut TokenStream) -> Result<Expression> {
-    let expr = parse_logic_or(tokens)?;
+    let expr = parse_binary(tokens, 0)?;
 
     if let Some(token) = tokens.peek() {
         if token.is_of_type(TokenType::Equal) {
             tokens.force_next()?;
-            let init = parse_assignment(tokens)?;
+            let init = nested(tokens, parse_assignment)?;
             let span = CodeSpan::combine(expr.get_location(), init.get_location());
             return if let Expression::Identifier(ident) = expr {
                 Ok(Expression::Assignment(Assignment {
@@ -91,92 +47,42 @@ fn parse_assignment(tokens: &mut TokenStream) -> Result<Expression> {
     Ok(expr)
 }
 
-fn parse_equality(tokens: &mut TokenStream) -> Result<Expression> {
-    let mut expr = try_parse!(parse_comparison, tokens)?;
-
-    while let Some(op) = tokens.peek() {
-        if op.is_of_type(TokenType::EqualEqual) || op.is_of_type(TokenType::BangEqual) {
-            tokens.next();
-            let right = parse_comparison(tokens)?;
-            let span = CodeSpan::combine(expr.get_location(), right.get_location());
-            expr = Expression::BinaryOperation(Binary {
-                operator: BinaryOperator::try_from(&op).unwrap(),
-                left: Box::new(expr),
-                right: Box::new(right),
-                location: span,
-            });
-        } else {
-            break;
-        }
-    }
-
-    Ok(expr)
-}
-
-fn parse_comparison(tokens: &mut TokenStream) -> Result<Expression> {
-    let mut expr = try_parse!(parse_term, tokens)?;
-
-    while let Some(op) = tokens.peek() {
-        if op.is_of_type(TokenType::Greater)
-            || op.is_of_type(TokenType::GreaterEqual)
-            || op.is_of_type(TokenType::Less)
-            || op.is_of_type(TokenType::LessEqual)
-        {
-            tokens.next();
-            let right = parse_term(tokens)?;
-            let span = CodeSpan::combine(expr.get_location(), right.get_location());
-            expr = Expression::BinaryOperation(Binary {
-                operator: BinaryOperator::try_from(&op).unwrap(),
-                left: Box::new(expr),
-                right: Box::new(right),
-                location: span,
-            });
-        } else {
-            break;
+/// Returns the binding power of a binary operator: operators with a higher
+/// binding power take precedence over those with a lower one. All binary
+/// operators are left-associative.
+fn binding_power(token_type: &TokenType) -> Option<u8> {
+    match token_type {
+        TokenType::Or => Some(1),
+        TokenType::And => Some(2),
+        TokenType::EqualEqual | TokenType::BangEqual => Some(3),
+        TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => {
+            Some(4)
         }
+        TokenType::Plus | TokenType::Minus => Some(5),
+        TokenType::Star | TokenType::Slash | TokenType::Percent => Some(6),
+        _ => None,
     }
-
-    Ok(expr)
 }
 
-fn parse_term(tokens: &mut TokenStream) -> Result<Expression> {
-    let mut expr = parse_factor(tokens)?;
-
-    while let Some(op) = tokens.peek() {
-        if op.is_of_type(TokenType::Plus) || op.is_of_type(TokenType::Minus) {
-            tokens.next();
-            let right = parse_factor(tokens)?;
-            let span = CodeSpan::combine(expr.get_location(), right.get_location());
-            expr = Expression::BinaryOperation(Binary {
-                operator: BinaryOperator::try_from(&op).unwrap(),
-                left: Box::new(expr),
-                right: Box::new(right),
-                location: span,
-            });
-        } else {
-            break;
-        }
-    }
-
-    Ok(expr)
-}
-
-fn parse_factor(tokens: &mut TokenStream) -> Result<Expression> {
+/// Parses a chain of binary operations whose operators bind at least as
+/// tightly as `min_power`.
+fn parse_binary(tokens: &mut TokenStream, min_power: u8) -> Result<Expression> {
     let mut expr = parse_unary(tokens)?;
 
     while let Some(op) = tokens.peek() {
-        if op.is_of_type(TokenType::Star) || op.is_of_type(TokenType::Slash) {
-            tokens.next();
-            let right = parse_unary(tokens)?;
-            let span = CodeSpan::combine(expr.get_location(), right.get_location());
-            expr = Expression::BinaryOperation(Binary {
-                operator: BinaryOperator::try_from(&op).unwrap(),
-                left: Box::new(expr),
-                right: Box::new(right),
-                location: span,
-            })
-        } else {
-            break;
+        match binding_power(op.get_type()) {
+            Some(power) if power >= min_power => {
+                tokens.next();
+                let right = parse_binary(tokens, power + 1)?;
+                let span = CodeSpan::combine(expr.get_location(), right.get_location());
+                expr = Expression::BinaryOperation(Binary {
+                    operator: BinaryOperator::try_from(&op).unwrap(),
+                    left: Box::new(expr),
+                    right: Box::new(right),
+                    location: span,
+                });
+            }
+            _ => break,
         }
     }
 
@@ -185,8 +91,11 @@ fn parse_factor(tokens: &mut TokenStream) -> Result<Expression> {
 
 fn parse_unary(tokens: &mut TokenStream) -> Result<Expression> {
     let tok = tokens.force_next()?;
-    if tok.is_of_type(TokenType::Bang) || tok.is_of_type(TokenType::Minus) {
-        let expr = parse_unary(tokens)?;
+    if tok.is_of_type(TokenType::Bang)
+        || tok.is_of_type(TokenType::Minus)
+        || tok.is_of_type(TokenType::Plus)
+    {
+        let expr = nested(tokens, parse_unary)?;
         Ok(Expression::UnaryOperation(Unary {
             op: UnaryOperator::try_from(&tok).unwrap(),
             expr: Box::new(expr),
@@ -221,9 +130,10 @@ fn parse_call(tokens: &mut TokenStream) -> Result<Expression> {
                     name: Identifier {
                         ident,
                         location: span,
+                        binding: Default::default(),
                     },
+                    location: CodeSpan::combine(expr.span(), span),
                     object: Box::new(expr),
-                    location: span,
                 }),
                 tt => return Err(ParsingError::UnexpectedToken(Token::new(tt, span))),
             };
@@ -252,10 +162,10 @@ fn parse_arguments(tokens: &mut TokenStream) -> Result<Vec<Expression>> {
                 }
             }
             if arguments.len() >= 255 {
-                Err(ParsingError::TooManyArguments(CodeSpan::combine(
-                    arguments.first().unwrap().get_location(),
-                    arguments.last().unwrap().get_location(),
-                )))
+                Err(ParsingError::TooManyArguments(
+                    CodeSpan::enclosing(arguments.iter().map(Expression::get_location))
+                        .expect("there is at least one argument"),
+                ))
             } else {
                 Ok(arguments)
             }
@@ -274,13 +184,34 @@ fn parse_primary(tokens: &mut TokenStream) -> Result<Expression> {
         TokenType::Identifier(s) => Ok(Expression::Identifier(Identifier {
             ident: s,
             location: span,
+            binding: Default::default(),
         })),
         TokenType::False => Ok(Expression::Literal(Literal::new(False, span))),
         TokenType::True => Ok(Expression::Literal(Literal::new(True, span))),
         TokenType::Nil => Ok(Expression::Literal(Literal::new(Nil, span))),
+        TokenType::This => Ok(Expression::This(span)),
+        TokenType::Super => {
+            consume(tokens, TokenType::Dot)?;
+            let method = tokens.force_next()?;
+            let method_span = method.get_span();
+            match method.consume() {
+                TokenType::Identifier(ident) => Ok(Expression::Super(Super {
+                    method: Identifier {
+                        ident,
+                        location: method_span,
+                        binding: Default::default(),
+                    },
+                    location: CodeSpan::combine(span, method_span),
+                })),
+                tt => Err(ParsingError::UnexpectedToken(Token::new(tt, method_span))),
+            }
+        }
 
         TokenType::Number(n) => Ok(Expression::Literal(Literal::new(NumberLiteral(n), span))),
-        TokenType::String(s) => Ok(Expression::Literal(Literal::new(StringLiteral(s), span))),
+        TokenType::String(s) => Ok(Expression::Literal(Literal::new(
+            StringLiteral(Rc::new(s)),
+            span,
+        ))),
 
         TokenType::LeftParen => {
             let expr = parse_expression(tokens)?;
@@ -304,6 +235,26 @@ mod tests {
     use super::*;
     use crate::parsing::tests::*;
 
+    fn parse_binding_at_least(tokens: &mut TokenStream, operator: TokenType) -> Result<Expression> {
+        parse_binary(tokens, binding_power(&operator).unwrap())
+    }
+
+    fn parse_factor(tokens: &mut TokenStream) -> Result<Expression> {
+        parse_binding_at_least(tokens, TokenType::Star)
+    }
+
+    fn parse_term(tokens: &mut TokenStream) -> Result<Expression> {
+        parse_binding_at_least(tokens, TokenType::Plus)
+    }
+
+    fn parse_comparison(tokens: &mut TokenStream) -> Result<Expression> {
+        parse_binding_at_least(tokens, TokenType::Less)
+    }
+
+    fn parse_equality(tokens: &mut TokenStream) -> Result<Expression> {
+        parse_binding_at_least(tokens, TokenType::EqualEqual)
+    }
+
     gen_tests!(
         primary,
         parse_primary,
@@ -311,10 +262,34 @@ mod tests {
         "false",
         "nil",
         "\"hi\"",
+        r#""a\"b\\c\n\t""#,
         "42",
-        "hello"
+        "hello",
+        "this",
+        "super.method"
     );
 
+    #[test]
+    fn number_literals_scan_back() {
+        for (code, printed) in [
+            ("0.5", "0.5"),
+            ("5.0", "5"),
+            ("12.25", "12.25"),
+            ("0.001", "0.001"),
+        ] {
+            let number = |code: &str| match parse_primary(&mut TokenStream::new(code)).unwrap() {
+                Expression::Literal(Literal {
+                    value: crate::ast::LiteralValue::NumberLiteral(n),
+                    ..
+                }) => n,
+                expr => panic!("expected a number, got {}", expr),
+            };
+            let expr = parse_primary(&mut TokenStream::new(code)).unwrap();
+            assert_eq!(expr.to_string(), printed);
+            assert_eq!(number(printed), number(code));
+        }
+    }
+
     gen_tests!(
         unary,
         parse_unary,
@@ -323,7 +298,10 @@ mod tests {
         "!!true",
         "-1",
         "--1",
-        "!(1 + 1)"
+        "!(1 + 1)",
+        "+5",
+        "-+5",
+        "+(1 + 1)"
     );
 
     gen_tests!(
@@ -334,6 +312,9 @@ mod tests {
         "1 / 1",
         "1 * 1 / 1",
         "1 * (1 / 1)",
+        "5 % 3",
+        "1 * 5 % 3",
+        "1 * (5 % 3)",
         "-(1 / 1)"
     );
 
@@ -392,6 +373,35 @@ mod tests {
         "a or b or c"
     );
 
+    #[test]
+    fn precedence() {
+        let cases = [
+            ("1 + 2 * 3", "1 + (2 * 3)"),
+            ("1 * 2 + 3", "(1 * 2) + 3"),
+            ("1 - 2 - 3", "(1 - 2) - 3"),
+            ("a or b and c", "a or (b and c)"),
+            ("a == b < c", "a == (b < c)"),
+            ("a and b == c", "a and (b == c)"),
+        ];
+        for (code, grouped) in cases {
+            let expr = parse_expression(&mut TokenStream::new(code)).unwrap();
+            let explicit = parse_expression(&mut TokenStream::new(grouped)).unwrap();
+            assert_eq!(tree(&expr), tree(&explicit), "{}", code);
+        }
+    }
+
+    /// Fully parenthesized representation of an expression, to compare trees
+    /// without relying on the printing priorities.
+    fn tree(expr: &Expression) -> String {
+        match expr {
+            Expression::BinaryOperation(b) => {
+                format!("({} {} {})", tree(&b.left), b.operator, tree(&b.right))
+            }
+            Expression::UnaryOperation(u) => format!("({}{})", u.op, tree(&u.expr)),
+            e => e.to_string(),
+        }
+    }
+
     gen_tests!(
         calls,
         parse_expression,
@@ -403,11 +413,50 @@ mod tests {
 
     gen_tests!(gets, parse_expression, "a.b", "a.b.c", "a().b.c()");
 
+    #[test]
+    fn call_location() {
+        let expr = parse_expression(&mut TokenStream::new("a.b()")).unwrap();
+        assert_eq!(expr.to_string(), "a.b()");
+        assert!(
+            matches!(&expr, Expression::Call(call) if matches!(*call.callee, Expression::Get(_)))
+        );
+        assert_eq!(expr.get_location().to_string(), "[1,0]-[1,5]");
+        let expr = parse_expression(&mut TokenStream::new("(a + b).c")).unwrap();
+        assert_eq!(expr.get_location().to_string(), "[1,1]-[1,9]");
+    }
+
+    gen_tests!(
+        chained_gets_and_calls,
+        parse_expression,
+        "a.b.c(x)",
+        "a(1).b(2).c",
+        "f(a.b, g(c).d)(e)",
+        "this.a.b()",
+        "super.a(b).c",
+        "(a + b).c",
+        "(a or b)(c)",
+        "(-a).b = c"
+    );
+
     gen_tests!(
         sets,
         parse_expression,
         "a.b = c",
         "a().b().c = d()",
-        "a.b = c.d = e"
+        "a.b = c.d = e",
+        "a.b.c = 1",
+        "a.b.c = d.e = f = 1"
     );
+
+    #[test]
+    fn chained_sets_are_right_associative() {
+        match parse_expression(&mut TokenStream::new("a.b.c = d.e = 1")).unwrap() {
+            Expression::Set(set) => {
+                assert_eq!(set.object.to_string(), "a.b");
+                assert_eq!(set.name.ident, "c");
+                assert!(matches!(*set.value, Expression::Set(_)));
+            }
+            _ => panic!("expected a set"),
+        }
+    }
 }
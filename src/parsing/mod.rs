@@ -7,36 +7,72 @@ pub use expressions::parse_expression;
 pub use parsing_error::ParsingError;
 
 use crate::{
-    ast::statements::Statements,
+    ast::statements::{Statement, Statements},
     parsing::declarations::parse_declaration,
     scanning::{Token, TokenStream, TokenType},
 };
 
-macro_rules! try_parse {
-    // TODO maybe remove this ? Not actually necessary atm, maybe when parser is complete
-    ($function:ident, $token_stream:ident) => {{
-        let save = $token_stream.save_position();
-        match $function($token_stream) {
-            Ok(expr) => Ok(expr),
-            Err(e) => {
-                $token_stream.load_position(save);
-                Err(e)
-            }
-        }
-    }};
-}
-pub(crate) use try_parse;
-
 type Result<T> = std::result::Result<T, ParsingError>;
 
+/// Parses a whole program. If the code ends in the middle of a statement,
+/// errors with `ParsingError::Incomplete`.
 pub fn parse(tokens: &mut TokenStream) -> Result<Statements> {
     let mut stmts = Vec::new();
 
     while tokens.has_next() {
-        stmts.push(parse_declaration(tokens)?);
+        stmts.push(parse_declaration(tokens).map_err(incomplete)?);
     }
 
-    Ok(Statements { stmts })
+    Ok(Statements {
+        stmts,
+        closing_brace: None,
+    })
+}
+
+/// Parses code typed at the prompt. Like `parse`, but a missing semicolon at
+/// the very end of the code is also reported as `ParsingError::Incomplete`,
+/// so that the statement can go on with the next line.
+pub fn parse_input(tokens: &mut TokenStream) -> Result<Statements> {
+    let mut stmts = Vec::new();
+
+    while tokens.has_next() {
+        stmts.push(parse_declaration(tokens).map_err(|e| incomplete_input(e, tokens))?);
+    }
+
+    Ok(Statements {
+        stmts,
+        closing_brace: None,
+    })
+}
+
+/// Parses exactly one statement or declaration from the code. If the code ends
+/// before the statement is complete, errors with `ParsingError::Incomplete` so
+/// that the caller can ask for more input.
+pub fn parse_one_statement(code: &str) -> Result<Statement> {
+    let mut tokens = TokenStream::new(code);
+    let stmt = parse_declaration(&mut tokens).map_err(|e| incomplete_input(e, &mut tokens))?;
+    match tokens.next() {
+        Some(token) => Err(ParsingError::UnexpectedToken(token)),
+        None => Ok(stmt),
+    }
+}
+
+/// Reports reaching the end of the token stream as incomplete input.
+fn incomplete(error: ParsingError) -> ParsingError {
+    match error {
+        ParsingError::UnexpectedEndOfTokenStream(loc) => ParsingError::Incomplete(loc),
+        e => e,
+    }
+}
+
+/// Reports reaching the end of the token stream as incomplete input, including
+/// a missing semicolon at the very end of the code, which more input could
+/// still provide.
+fn incomplete_input(error: ParsingError, tokens: &mut TokenStream) -> ParsingError {
+    match error {
+        ParsingError::ExpectedSemicolon(loc) if !tokens.has_next() => ParsingError::Incomplete(loc),
+        e => incomplete(e),
+    }
 }
 
 /// Consumes the first token of the stream if it is of the right type, else
@@ -55,6 +91,26 @@ fn consume(tokens: &mut TokenStream, token: TokenType) -> Result<Token> {
     }
 }
 
+/// Consumes the semicolon ending a statement, or errors with
+/// `ParsingError::ExpectedSemicolon` pointing just after the previous token.
+fn consume_semicolon(tokens: &mut TokenStream) -> Result<Token> {
+    consume(tokens, TokenType::Semicolon)
+        .map_err(|_| ParsingError::ExpectedSemicolon(tokens.previous_position()))
+}
+
+/// Runs a parsing function one nesting level deeper, so that deeply nested
+/// code fails with `ParsingError::NestingTooDeep` instead of overflowing the
+/// stack.
+fn nested<T>(
+    tokens: &mut TokenStream,
+    parse: impl FnOnce(&mut TokenStream) -> Result<T>,
+) -> Result<T> {
+    tokens.enter_nesting()?;
+    let result = parse(tokens);
+    tokens.exit_nesting();
+    result
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -90,6 +146,133 @@ pub mod tests {
     pub(crate) use gen_tests;
     pub(crate) use test_cases;
 
+    #[test]
+    fn deep_nesting() {
+        let code = format!("{}x", "!".repeat(100_000));
+        assert!(matches!(
+            parse_expression(&mut TokenStream::new(&code)),
+            Err(ParsingError::NestingTooDeep(_))
+        ));
+        let code = format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000));
+        assert!(matches!(
+            parse_expression(&mut TokenStream::new(&code)),
+            Err(ParsingError::NestingTooDeep(_))
+        ));
+        let code = format!("{}1;", "a = ".repeat(10_000));
+        assert!(matches!(
+            parse(&mut TokenStream::new(&code)),
+            Err(ParsingError::NestingTooDeep(_))
+        ));
+        for (open, close) in [("{", "}"), ("fun f() {", "}"), ("class A { m() {", "}}")] {
+            let code = format!("{}{}", open.repeat(10_000), close.repeat(10_000));
+            assert!(
+                matches!(
+                    parse(&mut TokenStream::new(&code)),
+                    Err(ParsingError::NestingTooDeep(_))
+                ),
+                "{}",
+                open
+            );
+        }
+    }
+
+    #[test]
+    fn reasonable_nesting() {
+        let code = format!("{}1{}", "(".repeat(80), ")".repeat(80));
+        assert!(parse_expression(&mut TokenStream::new(&code)).is_ok());
+        let code = format!("{}{}", "{".repeat(80), "}".repeat(80));
+        assert!(parse(&mut TokenStream::new(&code)).is_ok());
+    }
+
+    #[test]
+    fn configurable_nesting() {
+        let mut tokens = TokenStream::new("!!!!x").with_max_depth(3);
+        match parse_expression(&mut tokens) {
+            Err(ParsingError::NestingTooDeep(span)) => assert_eq!(span.to_string(), "[1,3]-[1,4]"),
+            res => panic!(
+                "expected a nesting error, got {:?}",
+                res.map(|e| e.to_string())
+            ),
+        }
+        let mut tokens = TokenStream::new("!!x").with_max_depth(3);
+        assert!(parse_expression(&mut tokens).is_ok());
+        let mut tokens = TokenStream::new("{{{{}}}}").with_max_depth(3);
+        assert!(matches!(
+            parse(&mut tokens),
+            Err(ParsingError::NestingTooDeep(_))
+        ));
+    }
+
+    #[test]
+    fn one_statement() {
+        assert_eq!(
+            parse_one_statement("{ print 1; }").unwrap().to_string(),
+            "{\nprint 1;\n}"
+        );
+        assert_eq!(
+            parse_one_statement("var a = 1;").unwrap().to_string(),
+            "var a = 1;"
+        );
+    }
+
+    #[test]
+    fn one_statement_incomplete() {
+        assert!(matches!(
+            parse_one_statement("{ print 1;"),
+            Err(ParsingError::Incomplete(_))
+        ));
+        assert!(matches!(
+            parse_one_statement("print (1 +"),
+            Err(ParsingError::Incomplete(_))
+        ));
+        assert!(matches!(
+            parse_one_statement("print 1"),
+            Err(ParsingError::Incomplete(_))
+        ));
+    }
+
+    #[test]
+    fn one_statement_invalid() {
+        assert!(matches!(
+            parse_one_statement("print 1 2;"),
+            Err(ParsingError::ExpectedSemicolon(_))
+        ));
+        assert!(matches!(
+            parse_one_statement("print 1; print 2;"),
+            Err(ParsingError::UnexpectedToken(_))
+        ));
+    }
+
+    #[test]
+    fn empty_program() {
+        for code in ["", "  \n\t", "// comment", "// comment\n// other\n"] {
+            let program = parse(&mut TokenStream::new(code)).unwrap();
+            assert!(program.stmts.is_empty(), "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn program_incomplete() {
+        assert!(matches!(
+            parse(&mut TokenStream::new("print 1; fun f() {")),
+            Err(ParsingError::Incomplete(_))
+        ));
+        assert!(matches!(
+            parse_input(&mut TokenStream::new("print 1; print 2")),
+            Err(ParsingError::Incomplete(_))
+        ));
+    }
+
+    #[test]
+    fn program_missing_final_semicolon() {
+        match parse(&mut TokenStream::new("print 1")) {
+            Err(e @ ParsingError::ExpectedSemicolon(_)) => {
+                assert_eq!(e.to_string(), "expected ';' at [1,7]")
+            }
+            _ => panic!("expected a missing semicolon"),
+        }
+    }
+
     gen_tests!(
         multiple_statements,
         parse,
@@ -11,6 +11,34 @@ pub enum ParsingError {
     UnexpectedToken(Token),
     InvalidAssignmentTarget(CodeSpan),
     TooManyArguments(CodeSpan),
+    /// The input ended before the statement was complete, so more input might
+    /// make it valid.
+    Incomplete(Location),
+    /// The code is nested too deeply to be parsed safely.
+    NestingTooDeep(CodeSpan),
+    /// A statement is missing its semicolon, which should be at the given
+    /// location, just after the statement's last token.
+    ExpectedSemicolon(Location),
+    /// A keyword is used where a declaration expects a name, as in
+    /// `var and = 1;`.
+    KeywordAsIdentifier(Token),
+    /// A function declares the same parameter name twice.
+    DuplicateParameter(CodeSpan, String),
+    /// `this` is used outside of the methods of a class.
+    ThisOutsideClass(CodeSpan),
+    /// `super` is used outside of the methods of a class with a superclass.
+    SuperOutsideSubclass(CodeSpan),
+    /// `break` is used outside of a loop.
+    BreakOutsideLoop(CodeSpan),
+    /// `continue` is used outside of a loop.
+    ContinueOutsideLoop(CodeSpan),
+    /// `return` is used outside of a function.
+    ReturnOutsideFunction(CodeSpan),
+    /// A local variable is used in its own initializer, as in
+    /// `{ var a = a; }`.
+    SelfReferentialInitializer(CodeSpan, String),
+    /// A name is used but never declared.
+    UndeclaredName(CodeSpan, String),
 }
 
 impl Display for ParsingError {
@@ -22,6 +50,36 @@ impl Display for ParsingError {
             ParsingError::UnexpectedToken(token) => write!(f, "unexpected token: {}", token),
             ParsingError::InvalidAssignmentTarget(_) => write!(f, "invalid assignment target"),
             ParsingError::TooManyArguments(_) => write!(f, "too many arguments (max 255)"),
+            ParsingError::Incomplete(loc) => write!(f, "incomplete input at {}", loc),
+            ParsingError::NestingTooDeep(span) => write!(f, "nesting too deep at {}", span),
+            ParsingError::ExpectedSemicolon(loc) => write!(f, "expected ';' at {}", loc),
+            ParsingError::KeywordAsIdentifier(token) => {
+                write!(f, "keyword cannot be used as a name: {}", token)
+            }
+            ParsingError::DuplicateParameter(span, name) => {
+                write!(f, "duplicate parameter '{}' at {}", name, span)
+            }
+            ParsingError::ThisOutsideClass(span) => {
+                write!(f, "'this' used outside of a class at {}", span)
+            }
+            ParsingError::SuperOutsideSubclass(span) => {
+                write!(f, "'super' used outside of a subclass at {}", span)
+            }
+            ParsingError::BreakOutsideLoop(span) => {
+                write!(f, "'break' used outside of a loop at {}", span)
+            }
+            ParsingError::ContinueOutsideLoop(span) => {
+                write!(f, "'continue' used outside of a loop at {}", span)
+            }
+            ParsingError::ReturnOutsideFunction(span) => {
+                write!(f, "'return' used outside of a function at {}", span)
+            }
+            ParsingError::SelfReferentialInitializer(span, name) => {
+                write!(f, "'{}' used in its own initializer at {}", name, span)
+            }
+            ParsingError::UndeclaredName(span, name) => {
+                write!(f, "undeclared name '{}' at {}", name, span)
+            }
         }
     }
 }
@@ -1,74 +1,117 @@
 use super::{parsing_error::ParsingError, Result};
 use crate::{
     ast::{
-        expressions::{Expression, Literal},
-        statements::{Conditional, ForLoop, Statement, Statements, WhileLoop},
+        expressions::{Expression, Identifier, Literal},
+        statements::{Assert, Conditional, ForIn, Print, Statement, Statements, WhileLoop},
         LiteralValue,
     },
     code_span::CodeSpan,
     parsing::{
-        consume,
+        consume, consume_semicolon,
         declarations::{parse_declaration, parse_variable_declaration},
         expressions::parse_expression,
+        nested,
     },
     scanning::{TokenStream, TokenType},
 };
 
-pub fn parse_declarations(tokens: &mut TokenStream) -> Vec<Statement> {
+/// Parses declarations until one fails to parse, leaving the tokens of that
+/// one unconsumed. Code nested too deeply is still an error, as the caller
+/// would not be able to parse it either.
+pub fn parse_declarations(tokens: &mut TokenStream) -> Result<Vec<Statement>> {
     let mut stmts = Vec::new();
 
     let mut save = tokens.save_position();
-    while let Ok(stmt) = parse_declaration(tokens) {
-        stmts.push(stmt);
+    loop {
+        match parse_declaration(tokens) {
+            Ok(stmt) => stmts.push(stmt),
+            Err(e @ ParsingError::NestingTooDeep(_)) => return Err(e),
+            Err(_) => break,
+        }
         save = tokens.save_position()
     }
     tokens.load_position(save);
-    stmts
+    Ok(stmts)
 }
 
 pub fn parse_statement(tokens: &mut TokenStream) -> Result<Statement> {
+    parse_inner_statement(tokens, true)
+}
+
+/// Parses a statement. When `loop_else` is false, a loop at the end of the
+/// statement leaves a following `else` to the enclosing `if`, which is what
+/// happens with nested conditionals too.
+fn parse_inner_statement(tokens: &mut TokenStream, loop_else: bool) -> Result<Statement> {
     match tokens.peek() {
         None => Err(ParsingError::UnexpectedEndOfTokenStream(
             tokens.current_position(),
         )),
         Some(t) => match t.get_type() {
             TokenType::Print => parse_print(tokens),
-            TokenType::LeftBrace => {
-                tokens.next();
-                let stmts = parse_declarations(tokens); // TODO error here
-                let rbrace = tokens.force_next()?;
-                if rbrace.is_of_type(TokenType::RightBrace) {
-                    Ok(Statement::Block(Statements { stmts }))
-                } else {
-                    Err(ParsingError::UnexpectedToken(rbrace))
-                }
-            }
-            TokenType::If => parse_conditional(tokens),
-            TokenType::While => parse_while_loop(tokens),
-            TokenType::For => parse_for(tokens),
+            TokenType::Assert => parse_assert(tokens),
+            TokenType::LeftBrace => nested(tokens, parse_block),
+            TokenType::If => nested(tokens, |tokens| parse_conditional(tokens, loop_else)),
+            TokenType::While => nested(tokens, |tokens| parse_while_loop(tokens, loop_else)),
+            TokenType::For => nested(tokens, |tokens| parse_for(tokens, loop_else)),
             TokenType::Return => parse_return(tokens),
+            TokenType::Break => parse_break(tokens),
+            TokenType::Continue => parse_continue(tokens),
             _ => {
                 let expr = parse_expression(tokens)?;
-                consume(tokens, TokenType::Semicolon)?;
+                consume_semicolon(tokens)?;
                 Ok(Statement::Expression(expr))
             }
         },
     }
 }
 
+/// Parses a block. Its last statement may be an expression without a trailing
+/// semicolon, which is then parsed as an expression statement. Blocks are
+/// statements, so they have no value.
+fn parse_block(tokens: &mut TokenStream) -> Result<Statement> {
+    consume(tokens, TokenType::LeftBrace)?;
+    let mut stmts = parse_declarations(tokens)?;
+    if tokens
+        .peek()
+        .is_some_and(|t| !t.is_of_type(TokenType::RightBrace))
+    {
+        stmts.push(Statement::Expression(parse_expression(tokens)?));
+    }
+    let closing_brace = consume(tokens, TokenType::RightBrace)?;
+    Ok(Statement::Block(Statements {
+        stmts,
+        closing_brace: Some(closing_brace.get_span()),
+    }))
+}
+
 fn parse_print(tokens: &mut TokenStream) -> Result<Statement> {
     let token = tokens.force_next()?;
     match token.get_type() {
         TokenType::Print => {
             let expr = parse_expression(tokens)?;
-            consume(tokens, TokenType::Semicolon)?;
-            Ok(Statement::Print(expr))
+            let semicolon = consume_semicolon(tokens)?;
+            Ok(Statement::Print(Print {
+                expr,
+                location: CodeSpan::combine(token.get_span(), semicolon.get_span()),
+            }))
         }
         _ => Err(ParsingError::UnexpectedToken(token)),
     }
 }
 
-fn parse_conditional(tokens: &mut TokenStream) -> Result<Statement> {
+fn parse_assert(tokens: &mut TokenStream) -> Result<Statement> {
+    let token = consume(tokens, TokenType::Assert)?;
+    let condition = parse_expression(tokens)?;
+    let semicolon = consume_semicolon(tokens)?;
+    let source = condition.source_text(&tokens.source_lines());
+    Ok(Statement::Assert(Assert {
+        condition,
+        source,
+        location: CodeSpan::combine(token.get_span(), semicolon.get_span()),
+    }))
+}
+
+fn parse_conditional(tokens: &mut TokenStream, loop_else: bool) -> Result<Statement> {
     if let Some(token) = tokens.peek() {
         match token.get_type() {
             TokenType::If => {
@@ -76,14 +119,14 @@ fn parse_conditional(tokens: &mut TokenStream) -> Result<Statement> {
                 consume(tokens, TokenType::LeftParen)?;
                 let condition = parse_expression(tokens)?;
                 consume(tokens, TokenType::RightParen)?;
-                let then_statement = parse_statement(tokens)?;
+                let then_statement = parse_inner_statement(tokens, false)?;
                 let else_statement = if tokens
                     .peek()
                     .map(|token| token.is_of_type(TokenType::Else))
                     .unwrap_or(false)
                 {
                     tokens.next();
-                    Some(parse_statement(tokens)?)
+                    Some(parse_inner_statement(tokens, loop_else)?)
                 } else {
                     None
                 };
@@ -102,7 +145,7 @@ fn parse_conditional(tokens: &mut TokenStream) -> Result<Statement> {
     }
 }
 
-fn parse_while_loop(tokens: &mut TokenStream) -> Result<Statement> {
+fn parse_while_loop(tokens: &mut TokenStream, loop_else: bool) -> Result<Statement> {
     if let Some(token) = tokens.peek() {
         match token.get_type() {
             TokenType::While => {
@@ -110,10 +153,14 @@ fn parse_while_loop(tokens: &mut TokenStream) -> Result<Statement> {
                 consume(tokens, TokenType::LeftParen)?;
                 let condition = parse_expression(tokens)?;
                 consume(tokens, TokenType::RightParen)?;
-                let statement = parse_statement(tokens)?;
+                let statement = parse_inner_statement(tokens, loop_else)?;
+                let else_statement = parse_loop_else(tokens, loop_else)?;
                 Ok(Statement::WhileLoop(Box::new(WhileLoop {
                     condition,
                     statement,
+                    increment: None,
+                    else_statement,
+                    for_keyword: None,
                 })))
             }
             _ => Err(ParsingError::UnexpectedToken(token)),
@@ -125,12 +172,27 @@ fn parse_while_loop(tokens: &mut TokenStream) -> Result<Statement> {
     }
 }
 
-fn parse_for(tokens: &mut TokenStream) -> Result<Statement> {
+/// Parses a for loop, desugared into a while loop inside a block holding the
+/// initializer.
+fn parse_for(tokens: &mut TokenStream, loop_else: bool) -> Result<Statement> {
     if let Some(token) = tokens.peek() {
         match token.get_type() {
             TokenType::For => {
+                let keyword = token.get_span();
                 tokens.next();
                 consume(tokens, TokenType::LeftParen)?;
+                if let Some(variable) = parse_for_in_variable(tokens)? {
+                    let iterable = parse_expression(tokens)?;
+                    consume(tokens, TokenType::RightParen)?;
+                    let statement = parse_inner_statement(tokens, loop_else)?;
+                    let else_statement = parse_loop_else(tokens, loop_else)?;
+                    return Ok(Statement::ForIn(Box::new(ForIn {
+                        variable,
+                        iterable,
+                        statement,
+                        else_statement,
+                    })));
+                }
 
                 let initializer = if tokens
                     .peek()
@@ -173,13 +235,25 @@ fn parse_for(tokens: &mut TokenStream) -> Result<Statement> {
                 };
 
                 consume(tokens, TokenType::RightParen)?;
-                let body = parse_statement(tokens)?;
-                Ok(Statement::ForLoop(Box::new(ForLoop {
-                    initializer,
-                    condition,
+                let body = parse_inner_statement(tokens, loop_else)?;
+                let else_statement = parse_loop_else(tokens, loop_else)?;
+
+                let while_loop = Statement::WhileLoop(Box::new(WhileLoop {
+                    condition: condition.unwrap_or_else(|| {
+                        Expression::Literal(Literal::new(LiteralValue::True, keyword))
+                    }),
+                    statement: body,
                     increment,
-                    body,
-                })))
+                    else_statement,
+                    for_keyword: Some(keyword),
+                }));
+                Ok(match initializer {
+                    Some(initializer) => Statement::Block(Statements {
+                        stmts: vec![initializer, while_loop],
+                        closing_brace: None,
+                    }),
+                    None => while_loop,
+                })
             }
             _ => Err(ParsingError::UnexpectedToken(token)),
         }
@@ -190,8 +264,49 @@ fn parse_for(tokens: &mut TokenStream) -> Result<Statement> {
     }
 }
 
+/// Parses the `var name in` part of a for-in loop. `in` is not a reserved
+/// word, so this returns `None` without consuming anything if the loop is a
+/// regular for loop.
+fn parse_for_in_variable(tokens: &mut TokenStream) -> Result<Option<Identifier>> {
+    let save = tokens.save_position();
+    if consume(tokens, TokenType::Var).is_ok() {
+        if let (Some(name), Some(keyword)) = (tokens.next(), tokens.next()) {
+            let location = name.get_span();
+            if let (TokenType::Identifier(ident), TokenType::Identifier(keyword)) =
+                (name.consume(), keyword.consume())
+            {
+                if keyword == "in" {
+                    return Ok(Some(Identifier {
+                        ident,
+                        location,
+                        binding: Default::default(),
+                    }));
+                }
+            }
+        }
+    }
+    tokens.load_position(save);
+    Ok(None)
+}
+
+/// Parses the optional else clause following the body of a loop. It is left
+/// to an enclosing `if` when `loop_else` is false.
+fn parse_loop_else(tokens: &mut TokenStream, loop_else: bool) -> Result<Option<Statement>> {
+    if loop_else
+        && tokens
+            .peek()
+            .map(|token| token.is_of_type(TokenType::Else))
+            .unwrap_or(false)
+    {
+        tokens.next();
+        Ok(Some(parse_statement(tokens)?))
+    } else {
+        Ok(None)
+    }
+}
+
 fn parse_return(tokens: &mut TokenStream) -> Result<Statement> {
-    consume(tokens, TokenType::Return)?;
+    let keyword = consume(tokens, TokenType::Return)?;
     let expr = if tokens
         .peek()
         .map(|t| t.is_of_type(TokenType::Semicolon))
@@ -199,15 +314,27 @@ fn parse_return(tokens: &mut TokenStream) -> Result<Statement> {
     {
         Expression::Literal(Literal {
             value: LiteralValue::Nil,
-            location: CodeSpan::new(tokens.current_position(), tokens.current_position()),
+            location: keyword.get_span(),
         })
     } else {
         parse_expression(tokens)?
     };
-    consume(tokens, TokenType::Semicolon)?;
+    consume_semicolon(tokens)?;
     Ok(Statement::Return(expr))
 }
 
+fn parse_break(tokens: &mut TokenStream) -> Result<Statement> {
+    let token = consume(tokens, TokenType::Break)?;
+    consume_semicolon(tokens)?;
+    Ok(Statement::Break(token.get_span()))
+}
+
+fn parse_continue(tokens: &mut TokenStream) -> Result<Statement> {
+    let token = consume(tokens, TokenType::Continue)?;
+    consume_semicolon(tokens)?;
+    Ok(Statement::Continue(token.get_span()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::{super::tests::*, *};
@@ -228,6 +355,28 @@ mod tests {
         "print 1 + 1;"
     );
 
+    gen_tests!(
+        test_assert_statements,
+        parse_assert,
+        "assert x > 0;",
+        "assert f(1, 2);"
+    );
+
+    #[test]
+    fn test_assert_source() {
+        let code = "assert (x  >\n  0) ;";
+        match parse_statement(&mut TokenStream::new(code)).unwrap() {
+            Statement::Assert(assert) => assert_eq!(assert.source, "x  >\n  0"),
+            _ => panic!("expected an assert statement"),
+        }
+        for code in ["!ok", "obj.valid", "-a.b(c) < 0"] {
+            match parse_statement(&mut TokenStream::new(&format!("assert {};", code))).unwrap() {
+                Statement::Assert(assert) => assert_eq!(assert.source, code),
+                _ => panic!("expected an assert statement"),
+            }
+        }
+    }
+
     gen_tests!(
         test_blocks,
         parse_statement,
@@ -236,6 +385,27 @@ mod tests {
         "{\nvar x = 42;\nprint x;\n}"
     );
 
+    #[test]
+    fn test_block_final_expression() {
+        let block = parse_statement(&mut TokenStream::new("{ 1; 2 }")).unwrap();
+        assert_eq!(block.to_string(), "{\n1;\n2;\n}");
+        match block {
+            Statement::Block(Statements { stmts, .. }) => {
+                assert!(matches!(stmts.last(), Some(Statement::Expression(_))))
+            }
+            _ => panic!("expected a block"),
+        }
+    }
+
+    #[test]
+    fn test_block_missing_semicolon() {
+        assert!(matches!(
+            parse_statement(&mut TokenStream::new("{ 1 2 }")),
+            Err(ParsingError::UnexpectedToken(_))
+        ));
+        assert!(parse_statement(&mut TokenStream::new("{ print 1 }")).is_err());
+    }
+
     gen_tests!(
         test_conditionals,
         parse_statement,
@@ -255,7 +425,114 @@ mod tests {
         test_for_loop,
         parse_statement,
         "for (;;) print 1;",
-        "for (var i = 0; i < 10; i = i + 1) print i;"
+        "for (; a;) print 1;",
+        "for (; a; a = a + 1) {\n}",
+        "for (var i = 0; i < 10; i = i + 1) print i;",
+        "for (i = 0;;) print i;",
+        "{\nvar i = 0;\nfor (; i < 10;) print i;\n}",
+        "while (true) print 1;"
+    );
+
+    #[test]
+    fn for_loop_desugaring() {
+        let code = "for (var i = 0; i < 10; i = i + 1) print i;";
+        match parse_statement(&mut TokenStream::new(code)).unwrap() {
+            Statement::Block(block) => match block.stmts.as_slice() {
+                [Statement::VariableDeclaration(_), Statement::WhileLoop(while_loop)] => {
+                    assert!(while_loop.increment.is_some())
+                }
+                _ => panic!("expected an initializer and a while loop"),
+            },
+            _ => panic!("expected a block"),
+        }
+    }
+
+    #[test]
+    fn missing_semicolon() {
+        for (code, location) in [
+            ("print 1", "[1,7]"),
+            ("var x = 1", "[1,9]"),
+            ("print 1\nprint 2;", "[1,7]"),
+            ("return a", "[1,8]"),
+            ("a = 2 b;", "[1,5]"),
+        ] {
+            match parse_declaration(&mut TokenStream::new(code)) {
+                Err(ParsingError::ExpectedSemicolon(loc)) => {
+                    assert_eq!(loc.to_string(), location, "{}", code)
+                }
+                res => panic!(
+                    "expected a missing semicolon in {}, got {:?}",
+                    code,
+                    res.map(|stmt| stmt.to_string())
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn block_location() {
+        let code = "{\n  print 1;\n  var a = 2;\n}";
+        let block = parse_statement(&mut TokenStream::new(code)).unwrap();
+        assert_eq!(block.get_location().to_string(), "[2,2]-[3,11]");
+        let empty = parse_statement(&mut TokenStream::new("{}")).unwrap();
+        assert!(empty.get_location().is_synthetic());
+    }
+
+    #[test]
+    fn for_loop_implicit_condition() {
+        match parse_statement(&mut TokenStream::new("for (;;) print 1;")).unwrap() {
+            Statement::WhileLoop(while_loop) => {
+                assert_eq!(
+                    while_loop.condition.get_location().to_string(),
+                    "[1,0]-[1,3]"
+                )
+            }
+            _ => panic!("expected a while loop"),
+        }
+        match parse_statement(&mut TokenStream::new("for (; a;) print 1;")).unwrap() {
+            Statement::WhileLoop(while_loop) => {
+                assert!(!while_loop.condition.get_location().is_synthetic())
+            }
+            _ => panic!("expected a while loop"),
+        }
+    }
+
+    gen_tests!(
+        test_loop_else,
+        parse_statement,
+        "while (a) print a; else print b;",
+        "while (true) {\nbreak;\n} else {\nprint 1;\n}",
+        "for (; true; a = a + 1) break; else print 2;",
+        "while (a) {\ncontinue;\n}",
+        "if (c) while (a) print a; else print b;",
+        "if (c) {\nwhile (a) print a; else print b;\n}"
+    );
+
+    #[test]
+    fn loop_in_then_branch_leaves_else_to_if() {
+        let code = "if (c) while (false) print \"loop\"; else print \"if-else\";";
+        match parse_statement(&mut TokenStream::new(code)).unwrap() {
+            Statement::Conditional(cond) => {
+                assert!(cond.else_statement.is_some());
+                match &cond.then_statement {
+                    Statement::WhileLoop(while_loop) => {
+                        assert!(while_loop.else_statement.is_none())
+                    }
+                    _ => panic!("expected a while loop"),
+                }
+            }
+            _ => panic!("expected a conditional"),
+        }
+    }
+
+    gen_tests!(
+        test_for_in,
+        parse_statement,
+        "for (var key in object) print key;",
+        "for (var key in a.b) {\nprint key;\n}",
+        "for (var in in in) print in;",
+        "for (var key in object) print key; else print 1;",
+        "if (c) for (var key in object) print key; else print 1;"
     );
 
     gen_tests!(
@@ -268,8 +545,11 @@ mod tests {
 
     #[test]
     fn test_statements() {
-        let parsed = parse_declarations(&mut TokenStream::new("var a = 1;\n print a;\n"));
-        let stmts = Statements { stmts: parsed };
+        let parsed = parse_declarations(&mut TokenStream::new("var a = 1;\n print a;\n")).unwrap();
+        let stmts = Statements {
+            stmts: parsed,
+            closing_brace: None,
+        };
         assert_eq!("var a = 1;\nprint a;\n", stmts.to_string());
     }
 }
@@ -41,31 +41,77 @@ fn delimit_operator(
     }
 }
 
+/// Consumes the digits following a digit, along with the underscores
+/// separating them. An underscore is only consumed when a digit follows it.
 fn extend_with_digits(source: &mut LocationTrackingIterator<Chars>, s: &mut std::string::String) {
-    let mut peek = source.peek();
-    while peek.is_some() && peek.unwrap().is_ascii_digit() {
-        s.push(source.next().unwrap());
-        peek = source.peek();
+    loop {
+        match source.peek().copied() {
+            Some(c) if c.is_ascii_digit() => s.push(source.next().unwrap()),
+            Some('_') if source.peek_2().is_some_and(char::is_ascii_digit) => {
+                s.push(source.next().unwrap())
+            }
+            _ => break,
+        }
+    }
+}
+
+/// Returns the character an escape sequence stands for, given the character
+/// following the backslash.
+fn unescape(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        '\\' | '"' => Some(c),
+        _ => None,
     }
 }
 
+/// Returns the error token for a string reaching the end of the source.
+fn unterminated_string(source: &LocationTrackingIterator<Chars>, start: &mut Location) -> Token {
+    let span = consume_span(start, source.get_location());
+    Token::new(Invalid(ScanningError::UnterminatedString(span)), span)
+}
+
 /// Scans a text stream.
 /// start should be Location::start() unless resuming from a previous iterator's
 /// text.
 pub fn scan(source: &mut LocationTrackingIterator<Chars>, start: &mut Location) -> Option<Token> {
+    scan_token(source, start, false)
+}
+
+/// Scans a text stream like `scan`, but returns comments as `Comment` tokens
+/// instead of skipping them.
+pub fn scan_with_comments(
+    source: &mut LocationTrackingIterator<Chars>,
+    start: &mut Location,
+) -> Option<Token> {
+    scan_token(source, start, true)
+}
+
+fn scan_token(
+    source: &mut LocationTrackingIterator<Chars>,
+    start: &mut Location,
+    keep_comments: bool,
+) -> Option<Token> {
     while let Some(char) = source.next() {
         return match char {
             // Comments
             '/' if source.peek() == Some(&'/') => {
-                loop {
-                    let next = source.next();
-                    if next == Some('\n') {
-                        *start = source.get_location();
-                        break;
-                    } else if next.is_none() {
-                        return None;
-                    }
+                source.next();
+                let mut text = std::string::String::new();
+                while let Some(&c) = source.peek().filter(|c| **c != '\n') {
+                    text.push(c);
+                    source.next();
+                }
+                if keep_comments {
+                    return Some(Token::new(
+                        Comment(text),
+                        consume_span(start, source.get_location()),
+                    ));
                 }
+                *start = source.get_location();
                 continue;
             }
 
@@ -105,6 +151,10 @@ pub fn scan(source: &mut LocationTrackingIterator<Chars>, start: &mut Location)
                 Slash,
                 consume_span(start, source.get_location()),
             )),
+            '%' => Some(Token::new(
+                Percent,
+                consume_span(start, source.get_location()),
+            )),
 
             // Composite operators
             '!' => Some(Token::new(
@@ -137,22 +187,52 @@ pub fn scan(source: &mut LocationTrackingIterator<Chars>, start: &mut Location)
             // String literals
             '"' => {
                 let mut str = std::string::String::new();
-                while source.peek() != Some(&'"') {
-                    if let Some(c) = source.next() {
-                        str.push(c);
-                    } else {
-                        let span = consume_span(start, source.get_location());
-                        return Some(Token::new(
-                            Invalid(ScanningError::UnterminatedString(span)),
-                            span,
-                        ));
+                let mut invalid_escape = None;
+                loop {
+                    let escape_start = source.get_location();
+                    match source.next() {
+                        Some('"') => break,
+                        Some('\\') => match source.next() {
+                            Some(c) => match unescape(c) {
+                                Some(unescaped) => str.push(unescaped),
+                                None => {
+                                    let span = CodeSpan::new(escape_start, source.get_location());
+                                    invalid_escape
+                                        .get_or_insert(ScanningError::InvalidEscape(c, span));
+                                }
+                            },
+                            None => return Some(unterminated_string(source, start)),
+                        },
+                        Some(c) => str.push(c),
+                        None => return Some(unterminated_string(source, start)),
                     }
                 }
-                source.next();
-                Some(Token::new(
-                    TokenType::String(str),
-                    consume_span(start, source.get_location()),
-                ))
+                let span = consume_span(start, source.get_location());
+                match invalid_escape {
+                    Some(error) => Some(Token::new(Invalid(error), span)),
+                    None => Some(Token::new(TokenType::String(str), span)),
+                }
+            }
+
+            // Hexadecimal and binary number literals
+            '0' if matches!(source.peek(), Some('x' | 'X' | 'b' | 'B')) => {
+                let radix = match source.next() {
+                    Some('x' | 'X') => 16,
+                    _ => 2,
+                };
+                let mut digits = std::string::String::new();
+                while let Some(&c) = source.peek().filter(|c| c.is_ascii_alphanumeric()) {
+                    digits.push(c);
+                    source.next();
+                }
+                let span = consume_span(start, source.get_location());
+                match u64::from_str_radix(&digits, radix) {
+                    Ok(n) => Some(Token::new(Number(n as f64), span)),
+                    Err(_) => Some(Token::new(
+                        Invalid(ScanningError::InvalidNumber(span)),
+                        span,
+                    )),
+                }
             }
 
             // Number literals
@@ -169,7 +249,7 @@ pub fn scan(source: &mut LocationTrackingIterator<Chars>, start: &mut Location)
                     }
                 }
                 Some(Token::new(
-                    Number(str.parse::<f64>().unwrap()),
+                    Number(str.replace('_', "").parse::<f64>().unwrap()),
                     consume_span(start, source.get_location()),
                 ))
             }
@@ -189,7 +269,10 @@ pub fn scan(source: &mut LocationTrackingIterator<Chars>, start: &mut Location)
                 Some(Token::new(
                     match str.as_str() {
                         "and" => And,
+                        "assert" => Assert,
+                        "break" => Break,
                         "class" => Class,
+                        "continue" => Continue,
                         "else" => Else,
                         "false" => False,
                         "for" => For,
@@ -292,7 +375,7 @@ mod tests {
 
     #[test]
     fn all_operators() {
-        let code = "(){},.-+;*!!====/<<=>>=";
+        let code = "(){},.-+;*!!====/<<=>>=%";
         let expected = "\
         [1,0]-[1,1] LeftParen\n\
         [1,1]-[1,2] RightParen\n\
@@ -313,6 +396,7 @@ mod tests {
         [1,18]-[1,20] LessEqual\n\
         [1,20]-[1,21] Greater\n\
         [1,21]-[1,23] GreaterEqual\n\
+        [1,23]-[1,24] Percent\n\
         ";
         assert_equals(code, expected);
     }
@@ -385,6 +469,82 @@ mod tests {
         assert_equals(code, expected);
     }
 
+    #[test]
+    fn string_escapes() {
+        for (code, decoded) in [
+            (r#""a\nb""#, "a\nb"),
+            (r#""\t""#, "\t"),
+            (r#""\r""#, "\r"),
+            (r#""\\""#, "\\"),
+            (r#""a\"b""#, "a\"b"),
+            (r#""\0""#, "\0"),
+            (r#""\\n\\""#, "\\n\\"),
+        ] {
+            let tokens = scan_all(code);
+            assert_eq!(tokens.len(), 1, "{}", code);
+            assert_eq!(
+                *tokens[0].get_type(),
+                crate::scanning::TokenType::String(decoded.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_escape() {
+        let code = r#""a\qb" +"#;
+        let expected = "\
+        [1,0]-[1,6] Invalid(InvalidEscape('q', [1,2]-[1,4]))\n\
+        [1,7]-[1,8] Plus\n\
+        ";
+        assert_equals(code, expected);
+    }
+
+    #[test]
+    fn unterminated_escape() {
+        let code = r#""abc\"#;
+        let expected = "\
+        [1,0]-[1,5] Invalid(UnterminatedString([1,0]-[1,5]))\n\
+        ";
+        assert_equals(code, expected);
+    }
+
+    #[test]
+    fn hexadecimal_and_binary() {
+        let code = "0xFF 0x1a 0b1010 0XaB 0 0.5";
+        let expected = "\
+        [1,0]-[1,4] Number(255.0)\n\
+        [1,5]-[1,9] Number(26.0)\n\
+        [1,10]-[1,16] Number(10.0)\n\
+        [1,17]-[1,21] Number(171.0)\n\
+        [1,22]-[1,23] Number(0.0)\n\
+        [1,24]-[1,27] Number(0.5)\n\
+        ";
+        assert_equals(code, expected);
+    }
+
+    #[test]
+    fn malformed_hexadecimal_and_binary() {
+        let code = "0xG 0b102 0x;";
+        let expected = "\
+        [1,0]-[1,3] Invalid(InvalidNumber([1,0]-[1,3]))\n\
+        [1,4]-[1,9] Invalid(InvalidNumber([1,4]-[1,9]))\n\
+        [1,10]-[1,12] Invalid(InvalidNumber([1,10]-[1,12]))\n\
+        [1,12]-[1,13] Semicolon\n\
+        ";
+        assert_equals(code, expected);
+    }
+
+    #[test]
+    fn multiline_string() {
+        let code = "\"line1\nline2\" +\n  1";
+        let expected = "\
+        [1,0]-[2,6] String(\"line1\\nline2\")\n\
+        [2,7]-[2,8] Plus\n\
+        [3,2]-[3,3] Number(1.0)\n\
+        ";
+        assert_equals(code, expected);
+    }
+
     #[test]
     fn unterminated_string() {
         let code = r#""i swear i am compl"#;
@@ -424,6 +584,33 @@ mod tests {
         assert_equals(code, expected);
     }
 
+    #[test]
+    fn digit_separators() {
+        let code = "1_000_000 3_141.592_65";
+        let expected = "\
+        [1,0]-[1,9] Number(1000000.0)\n\
+        [1,10]-[1,22] Number(3141.59265)\n\
+        ";
+        assert_equals(code, expected);
+    }
+
+    #[test]
+    fn misplaced_digit_separators() {
+        let code = "_5 5_ 5__5 1_.5";
+        let expected = "\
+        [1,0]-[1,2] Identifier(\"_5\")\n\
+        [1,3]-[1,4] Number(5.0)\n\
+        [1,4]-[1,5] Identifier(\"_\")\n\
+        [1,6]-[1,7] Number(5.0)\n\
+        [1,7]-[1,10] Identifier(\"__5\")\n\
+        [1,11]-[1,12] Number(1.0)\n\
+        [1,12]-[1,13] Identifier(\"_\")\n\
+        [1,13]-[1,14] Dot\n\
+        [1,14]-[1,15] Number(5.0)\n\
+        ";
+        assert_equals(code, expected);
+    }
+
     #[test]
     fn number_literal_method_call() {
         let code = "\
@@ -495,7 +682,10 @@ mod tests {
     fn all_reserved_word() {
         let code = "\
         and\n\
+        assert\n\
+        break\n\
         class\n\
+        continue\n\
         else\n\
         false\n\
         for\n\
@@ -512,21 +702,24 @@ mod tests {
         while\n\
         ";
         let expected = "[1,0]-[1,3] And\n\
-        [2,0]-[2,5] Class\n\
-        [3,0]-[3,4] Else\n\
-        [4,0]-[4,5] False\n\
-        [5,0]-[5,3] For\n\
-        [6,0]-[6,3] Fun\n\
-        [7,0]-[7,2] If\n\
-        [8,0]-[8,3] Nil\n\
-        [9,0]-[9,2] Or\n\
-        [10,0]-[10,5] Print\n\
-        [11,0]-[11,6] Return\n\
-        [12,0]-[12,5] Super\n\
-        [13,0]-[13,4] This\n\
-        [14,0]-[14,4] True\n\
-        [15,0]-[15,3] Var\n\
-        [16,0]-[16,5] While\n\
+        [2,0]-[2,6] Assert\n\
+        [3,0]-[3,5] Break\n\
+        [4,0]-[4,5] Class\n\
+        [5,0]-[5,8] Continue\n\
+        [6,0]-[6,4] Else\n\
+        [7,0]-[7,5] False\n\
+        [8,0]-[8,3] For\n\
+        [9,0]-[9,3] Fun\n\
+        [10,0]-[10,2] If\n\
+        [11,0]-[11,3] Nil\n\
+        [12,0]-[12,2] Or\n\
+        [13,0]-[13,5] Print\n\
+        [14,0]-[14,6] Return\n\
+        [15,0]-[15,5] Super\n\
+        [16,0]-[16,4] This\n\
+        [17,0]-[17,4] True\n\
+        [18,0]-[18,3] Var\n\
+        [19,0]-[19,5] While\n\
         ";
         assert_equals(code, expected);
     }
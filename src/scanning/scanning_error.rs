@@ -9,6 +9,23 @@ use crate::code_span::CodeSpan;
 pub enum ScanningError {
     UnterminatedString(CodeSpan),
     InvalidCharacter(char, CodeSpan),
+    /// A backslash in a string literal is followed by a character that does
+    /// not form an escape sequence.
+    InvalidEscape(char, CodeSpan),
+    /// A hexadecimal or binary number literal has no digits or digits that
+    /// are not valid in its base.
+    InvalidNumber(CodeSpan),
+}
+
+impl ScanningError {
+    pub fn location(&self) -> CodeSpan {
+        match *self {
+            ScanningError::UnterminatedString(span) => span,
+            ScanningError::InvalidCharacter(_, span) => span,
+            ScanningError::InvalidEscape(_, span) => span,
+            ScanningError::InvalidNumber(span) => span,
+        }
+    }
 }
 
 impl Display for ScanningError {
@@ -18,8 +35,65 @@ impl Display for ScanningError {
             ScanningError::InvalidCharacter(c, span) => {
                 write!(f, "invalid character '{}' at {}", c, span)
             }
+            ScanningError::InvalidNumber(span) => write!(f, "invalid number literal at {}", span),
+            ScanningError::InvalidEscape(c, span) => {
+                write!(f, "invalid escape sequence '\\{}' at {}", c, span)
+            }
         }
     }
 }
 
 impl Error for ScanningError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        location::Location,
+        scanning::{scan_all, TokenType},
+    };
+
+    fn first_error(code: &str) -> ScanningError {
+        scan_all(code)
+            .into_iter()
+            .find_map(|token| match token.consume() {
+                TokenType::Invalid(error) => Some(error),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn unterminated_string_location() {
+        let error = first_error("1 \"abc");
+        assert!(matches!(error, ScanningError::UnterminatedString(_)));
+        assert_eq!(
+            error.location(),
+            CodeSpan::new(Location::new(1, 2), Location::new(1, 6))
+        );
+    }
+
+    #[test]
+    fn invalid_escape_location() {
+        let error = first_error(r#"print "a\qb\z";"#);
+        assert!(matches!(error, ScanningError::InvalidEscape('q', _)));
+        assert_eq!(
+            error.location(),
+            CodeSpan::new(Location::new(1, 8), Location::new(1, 10))
+        );
+        assert_eq!(
+            error.to_string(),
+            "invalid escape sequence '\\q' at [1,8]-[1,10]"
+        );
+    }
+
+    #[test]
+    fn invalid_character_location() {
+        let error = first_error("1 + @");
+        assert!(matches!(error, ScanningError::InvalidCharacter('@', _)));
+        assert_eq!(
+            error.location(),
+            CodeSpan::new(Location::new(1, 4), Location::new(1, 5))
+        );
+    }
+}
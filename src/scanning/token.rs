@@ -17,6 +17,7 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Percent,
 
     Bang,
     BangEqual,
@@ -32,7 +33,10 @@ pub enum TokenType {
     Number(f64),
 
     And,
+    Assert,
+    Break,
     Class,
+    Continue,
     Else,
     False,
     Fun,
@@ -48,10 +52,80 @@ pub enum TokenType {
     Var,
     While,
 
+    /// A line comment, holding the text after `//`. Only scanned when
+    /// comments are kept.
+    Comment(String),
+
     Invalid(super::ScanningError),
 }
 
+impl TokenType {
+    /// Returns whether the token is a reserved word.
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            TokenType::And
+                | TokenType::Assert
+                | TokenType::Break
+                | TokenType::Class
+                | TokenType::Continue
+                | TokenType::Else
+                | TokenType::False
+                | TokenType::Fun
+                | TokenType::For
+                | TokenType::If
+                | TokenType::Nil
+                | TokenType::Or
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Super
+                | TokenType::This
+                | TokenType::True
+                | TokenType::Var
+                | TokenType::While
+        )
+    }
+
+    /// Returns whether the token is a symbolic operator. Punctuation such as
+    /// parentheses or semicolons is not included, nor are `and` and `or`,
+    /// which are keywords.
+    pub fn is_operator(&self) -> bool {
+        matches!(
+            self,
+            TokenType::Minus
+                | TokenType::Plus
+                | TokenType::Slash
+                | TokenType::Star
+                | TokenType::Percent
+                | TokenType::Bang
+                | TokenType::BangEqual
+                | TokenType::Equal
+                | TokenType::EqualEqual
+                | TokenType::Greater
+                | TokenType::GreaterEqual
+                | TokenType::Less
+                | TokenType::LessEqual
+        )
+    }
+
+    /// Returns whether the token denotes a literal value. `true`, `false` and
+    /// `nil` are both literals and keywords.
+    pub fn is_literal(&self) -> bool {
+        matches!(
+            self,
+            TokenType::String(_)
+                | TokenType::Number(_)
+                | TokenType::True
+                | TokenType::False
+                | TokenType::Nil
+        )
+    }
+}
+
 /// Represents a token along with its location in the source code.
+///
+/// Equality compares both the type and the span of the tokens, so that tests
+/// can check where tokens were scanned. Use `eq_type` to only compare types.
 #[derive(PartialEq, Clone)]
 pub struct Token {
     token: TokenType,
@@ -71,6 +145,12 @@ impl Token {
         self.token == token_type
     }
 
+    /// Returns whether both tokens have the same type, regardless of their
+    /// location.
+    pub fn eq_type(&self, other: &Token) -> bool {
+        self.token == other.token
+    }
+
     pub fn is_identifier(&self) -> bool {
         matches!(self.token, TokenType::Identifier(_))
     }
@@ -99,3 +179,52 @@ impl Debug for Token {
         write!(f, "{} {:?}", self.span, self.token)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::location::Location;
+
+    fn token(token_type: TokenType, line: usize, char: usize) -> Token {
+        Token::new(
+            token_type,
+            CodeSpan::new(Location::new(line, char), Location::new(line, char + 1)),
+        )
+    }
+
+    #[test]
+    fn eq_type_ignores_spans() {
+        let first = token(TokenType::Plus, 1, 0);
+        let second = token(TokenType::Plus, 3, 7);
+        assert!(first.eq_type(&second));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn eq_type_compares_values() {
+        let a = token(TokenType::Identifier("a".to_string()), 1, 0);
+        let also_a = token(TokenType::Identifier("a".to_string()), 2, 0);
+        let b = token(TokenType::Identifier("b".to_string()), 1, 0);
+        assert!(a.eq_type(&also_a));
+        assert!(!a.eq_type(&b));
+        assert!(!a.eq_type(&token(TokenType::Plus, 1, 0)));
+    }
+
+    #[test]
+    fn classification() {
+        assert!(TokenType::While.is_keyword());
+        assert!(TokenType::And.is_keyword());
+        assert!(!TokenType::Identifier("while".to_string()).is_keyword());
+        assert!(!TokenType::Plus.is_keyword());
+
+        assert!(TokenType::Plus.is_operator());
+        assert!(TokenType::BangEqual.is_operator());
+        assert!(!TokenType::Semicolon.is_operator());
+        assert!(!TokenType::Or.is_operator());
+
+        assert!(TokenType::Number(1.0).is_literal());
+        assert!(TokenType::String("a".to_string()).is_literal());
+        assert!(TokenType::Nil.is_literal() && TokenType::Nil.is_keyword());
+        assert!(!TokenType::Identifier("a".to_string()).is_literal());
+    }
+}
@@ -1,12 +1,13 @@
 use std::str::Chars;
 
 use crate::{
+    code_span::CodeSpan,
     location::Location,
     location_tracking_iterator::LocationTrackingIterator,
     scanning::{
-        scan,
+        scan, scan_with_comments,
         token::token_stream::Position::{End, Index},
-        Token,
+        Token, TokenType,
     },
 };
 
@@ -15,6 +16,10 @@ pub enum Position {
     Index(usize),
 }
 
+/// Default maximum nesting depth of the parsed code, low enough for the
+/// recursive descent parser not to overflow a 2 MiB stack in debug builds.
+pub const DEFAULT_MAX_DEPTH: usize = 100;
+
 pub struct TokenStreamState {
     position: usize,
 }
@@ -22,20 +27,72 @@ pub struct TokenStreamState {
 /// TokenStream is an iterator that returns lazily-scanned tokens and allows
 /// backtracking.
 pub struct TokenStream<'a> {
+    source: &'a str,
     it: LocationTrackingIterator<Chars<'a>>,
     loc: Location,
     vec: Vec<Token>,
     pos: Position,
+    depth: usize,
+    max_depth: usize,
+    comments: Option<Vec<Token>>,
 }
 
 impl<'a> TokenStream<'a> {
     pub fn new(text: &'a str) -> Self {
         TokenStream {
+            source: text,
             it: LocationTrackingIterator::new(text.chars()),
             loc: Location::start(),
             vec: vec![],
             pos: Position::End,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            comments: None,
+        }
+    }
+
+    /// Sets the maximum nesting depth allowed when parsing the stream.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Keeps the comments of the source, to be retrieved with `take_comments`.
+    /// They are still skipped when iterating over the tokens.
+    pub fn with_comments(mut self) -> Self {
+        self.comments = Some(Vec::new());
+        self
+    }
+
+    /// Returns the comments scanned so far, in order, if comments are kept.
+    pub fn take_comments(&mut self) -> Vec<Token> {
+        self.comments
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Returns the lines of the source being scanned.
+    pub fn source_lines(&self) -> Vec<&'a str> {
+        self.source.lines().collect()
+    }
+
+    /// Enters a nested construct, failing with `ParsingError::NestingTooDeep`
+    /// if the maximum depth is reached. Must be paired with `exit_nesting`.
+    pub fn enter_nesting(&mut self) -> Result<(), crate::parsing::ParsingError> {
+        if self.depth >= self.max_depth {
+            let span = match self.peek() {
+                Some(token) => token.span,
+                None => CodeSpan::new(self.current_position(), self.current_position()),
+            };
+            return Err(crate::parsing::ParsingError::NestingTooDeep(span));
         }
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub fn exit_nesting(&mut self) {
+        self.depth -= 1;
     }
 
     pub fn force_next(&mut self) -> Result<<Self as Iterator>::Item, crate::parsing::ParsingError> {
@@ -60,13 +117,20 @@ impl<'a> TokenStream<'a> {
 
     /// Internal. Immediately scan next token from source
     fn parse_next_token(&mut self) -> Option<<Self as Iterator>::Item> {
-        if let Some(token) = scan(&mut self.it, &mut self.loc) {
-            let clone = token.clone();
-            self.vec.push(token);
-            Some(clone) // Last should NEVER return None
-        } else {
-            None
-        }
+        let token = match &mut self.comments {
+            Some(comments) => loop {
+                let token = scan_with_comments(&mut self.it, &mut self.loc)?;
+                if let TokenType::Comment(_) = token.get_type() {
+                    comments.push(token);
+                } else {
+                    break token;
+                }
+            },
+            None => scan(&mut self.it, &mut self.loc)?,
+        };
+        let clone = token.clone();
+        self.vec.push(token);
+        Some(clone) // Last should NEVER return None
     }
 
     pub fn set_pos(&mut self, pos: Position) {
@@ -107,9 +171,34 @@ impl<'a> TokenStream<'a> {
         }
     }
 
+    /// Goes back to the first token. The tokens already scanned are kept, so
+    /// they are not scanned again.
+    pub fn reset(&mut self) {
+        self.pos = if self.vec.is_empty() { End } else { Index(0) };
+    }
+
+    /// Returns the end of the last token returned by the stream, ignoring
+    /// tokens that were only peeked at.
+    pub fn previous_position(&self) -> Location {
+        let previous = match self.pos {
+            Position::Index(n) => n.checked_sub(1),
+            Position::End => self.vec.len().checked_sub(1),
+        };
+        match previous {
+            None => Location::start(),
+            Some(n) => self.vec[n].span.end,
+        }
+    }
+
     pub fn has_next(&mut self) -> bool {
         self.peek().is_some()
     }
+
+    /// Returns every token scanned so far, including tokens that were only
+    /// peeked at or that were backtracked over.
+    pub fn scanned(&self) -> &[Token] {
+        &self.vec
+    }
 }
 
 impl<'a> Iterator for TokenStream<'a> {
@@ -155,9 +244,7 @@ mod tests {
         [1,8]-[1,9] Identifier(\"c\")\n\
         ";
         let mut token_stream = TokenStream::new(text);
-        let mut vec = vec![];
-        vec.push(token_stream.next().unwrap());
-        vec.push(token_stream.next().unwrap());
+        let mut vec = vec![token_stream.next().unwrap(), token_stream.next().unwrap()];
         token_stream.back();
         vec.extend(token_stream);
         assert_eq!(crate::scanning::to_string(vec), expected);
@@ -196,10 +283,11 @@ mod tests {
         [1,0]-[1,1] Identifier(\"a\")\n\
         ";
         let mut token_stream = TokenStream::new(text);
-        let mut vec = vec![];
-        vec.push(token_stream.peek().unwrap());
-        vec.push(token_stream.peek().unwrap());
-        vec.push(token_stream.next().unwrap());
+        let vec = vec![
+            token_stream.peek().unwrap(),
+            token_stream.peek().unwrap(),
+            token_stream.next().unwrap(),
+        ];
         assert_eq!(crate::scanning::to_string(vec), expected);
 
         for _ in 0..4 {
@@ -242,6 +330,39 @@ mod tests {
         TokenStream::new("a").back()
     }
 
+    #[test]
+    fn scanned() {
+        let text = "a = b + c";
+        let expected = "\
+        [1,0]-[1,1] Identifier(\"a\")\n\
+        [1,2]-[1,3] Equal\n\
+        [1,4]-[1,5] Identifier(\"b\")\n\
+        ";
+        let mut token_stream = TokenStream::new(text);
+        assert!(token_stream.scanned().is_empty());
+        token_stream.next();
+        token_stream.next();
+        token_stream.peek();
+        token_stream.back();
+        assert_eq!(
+            crate::scanning::to_string(token_stream.scanned().to_vec()),
+            expected
+        );
+    }
+
+    #[test]
+    fn reset() {
+        let text = "a = b + c";
+        let mut token_stream = TokenStream::new(text);
+        token_stream.reset();
+        let first = token_stream.by_ref().collect::<Vec<Token>>();
+        token_stream.reset();
+        assert_eq!(token_stream.scanned(), first.as_slice());
+        let second = token_stream.by_ref().collect::<Vec<Token>>();
+        assert_eq!(first, second);
+        assert_eq!(first, scan_all(text));
+    }
+
     #[test]
     fn eof_peek_then_next() {
         let text = "1";
@@ -251,4 +372,26 @@ mod tests {
         token_stream.peek();
         assert_eq!(token_stream.next(), None);
     }
+
+    #[test]
+    fn comments() {
+        let text = "a // first\n// second\nb //";
+        let mut token_stream = TokenStream::new(text).with_comments();
+        assert_eq!(
+            token_stream.by_ref().collect::<Vec<Token>>(),
+            scan_all(text)
+        );
+        assert_eq!(
+            crate::scanning::to_string(token_stream.take_comments()),
+            "\
+            [1,2]-[1,10] Comment(\" first\")\n\
+            [2,0]-[2,9] Comment(\" second\")\n\
+            [3,2]-[3,4] Comment(\"\")\n\
+            "
+        );
+        assert!(token_stream.take_comments().is_empty());
+        let mut token_stream = TokenStream::new(text);
+        assert_eq!(token_stream.by_ref().count(), 2);
+        assert!(token_stream.take_comments().is_empty());
+    }
 }